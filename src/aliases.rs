@@ -0,0 +1,424 @@
+//! Imports aliases and abbreviations from other tools as leader combos.
+//!
+//! The aliases are proposed as the text form of the combos, with the alias names as the keys, so
+//! mostly the same keys can be used after migrating. The aliases of each program are nested under
+//! a prefix key, and any alias whose name is already taken is renamed.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use log::debug;
+
+use crate::{
+    edit::format_combo,
+    nodes::{CharWithModifiers, Node, parse_key_sequence},
+    stats::is_free,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Alias {
+    pub name: String,
+    pub command: String,
+}
+
+impl Alias {
+    fn new(name: &str, command: &str) -> Option<Alias> {
+        let (name, command) = (name.trim(), command.trim());
+
+        (!name.is_empty() && !command.is_empty()).then(|| Alias {
+            name: name.to_string(),
+            command: command.to_string(),
+        })
+    }
+}
+
+/// Splits a line into words like a POSIX shell, handling quotes and backslashes. Also handles
+/// zsh's `$'...'` quoting, which `alias` uses for values containing special characters.
+fn shell_words(line: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                words.extend(word.take());
+            }
+            '\'' => {
+                let word = word.get_or_insert_default();
+                for c in chars.by_ref() {
+                    if c == '\'' {
+                        break;
+                    }
+                    word.push(c);
+                }
+            }
+            '$' if chars.peek() == Some(&'\'') => {
+                chars.next();
+                let word = word.get_or_insert_default();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\'' => break,
+                        '\\' => match chars.next() {
+                            Some('n') => word.push('\n'),
+                            Some('t') => word.push('\t'),
+                            Some(c) => word.push(c),
+                            None => (),
+                        },
+                        c => word.push(c),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_default();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => (),
+                        },
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                if let Some(c) = chars.next() {
+                    word.get_or_insert_default().push(c);
+                }
+            }
+            c => word.get_or_insert_default().push(c),
+        }
+    }
+
+    words.extend(word);
+    words
+}
+
+/// Parses the output of `zsh -ic alias`, e.g. `gs='git status'`.
+pub fn parse_zsh_aliases(text: &str) -> Vec<Alias> {
+    text.lines()
+        .filter_map(|line| {
+            let words = shell_words(line);
+            let (name, command) = words.first()?.split_once('=')?;
+            Alias::new(name, command)
+        })
+        .collect()
+}
+
+/// Parses the output of `git config --get-regexp alias`, e.g. `alias.co checkout`. Aliases which
+/// start with `!` run a shell command; the rest are git subcommands.
+pub fn parse_git_aliases(text: &str) -> Vec<Alias> {
+    text.lines()
+        .filter_map(|line| {
+            let (name, value) = line.trim().split_once(char::is_whitespace)?;
+            let name = name.strip_prefix("alias.")?;
+
+            match value.trim().strip_prefix('!') {
+                Some(shell) => Alias::new(name, shell),
+                None => Alias::new(name, &format!("git {}", value.trim())),
+            }
+        })
+        .collect()
+}
+
+/// Parses the user abbreviations file of zsh-abbr, e.g. `abbr "gs"="git status"`.
+pub fn parse_zsh_abbr(text: &str) -> Vec<Alias> {
+    text.lines()
+        .filter_map(|line| {
+            let words = shell_words(line);
+            let mut words = words.iter().skip_while(|w| *w != "abbr").skip(1);
+            let definition = words.find(|w| !w.starts_with('-'))?;
+            let (name, command) = definition.split_once('=')?;
+            Alias::new(name, command)
+        })
+        .collect()
+}
+
+/// Parses the output of `abbr --show` in fish, e.g. `abbr -a -- gs 'git status'`.
+pub fn parse_fish_abbr(text: &str) -> Vec<Alias> {
+    text.lines()
+        .filter_map(|line| {
+            let words = shell_words(line);
+            if words.first().map(String::as_str) != Some("abbr") {
+                return None;
+            }
+
+            let rest: Vec<&String> = match words.iter().position(|w| w == "--") {
+                Some(i) => words[i + 1..].iter().collect(),
+                None => words[1..].iter().filter(|w| !w.starts_with('-')).collect(),
+            };
+            match rest.as_slice() {
+                [name, command, ..] => Alias::new(name, command),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// The proposed combos, and anything which the user should know about.
+#[derive(Debug, Default)]
+pub struct Proposal {
+    /// The text form of the combos, which can be appended to a leader key.
+    pub text: String,
+    pub count: usize,
+    pub warnings: Vec<String>,
+}
+
+/// The keys of an alias, which are typed as they are.
+fn sequence(keys: &str) -> Vec<CharWithModifiers> {
+    keys.chars().map(Into::into).collect()
+}
+
+/// The keys of an alias beneath the prefix of its program, unless they already start with it.
+fn prefixed(prefix: char, name: &str) -> String {
+    match name.starts_with(prefix) {
+        true => name.to_string(),
+        false => format!("{prefix}{name}"),
+    }
+}
+
+/// Why a combo can't be added for the keys, which aren't free (see [`is_free`]).
+fn conflict(tree: &Node, keys: &str) -> String {
+    let mut node = tree;
+
+    for (i, key) in sequence(keys).iter().enumerate() {
+        if let Some(ref command) = node.command
+            && node.children.is_empty()
+        {
+            let prefix: String = keys.chars().take(i).collect();
+            return format!("{prefix:?} runs {command:?} as soon as it's typed");
+        }
+        match node.children.get(key) {
+            Some(child) => node = child,
+            None => break,
+        }
+    }
+
+    match node.command {
+        Some(ref command) => format!("{keys:?} is already taken by {command:?}"),
+        None => format!("other combos start with {keys:?}"),
+    }
+}
+
+/// Proposes a combo for the alias under the keys `name`, unless the same combo is already taken.
+/// If the keys aren't free (see [`is_free`]), a digit is appended to them.
+fn propose_combo(
+    alias: &Alias,
+    name: &str,
+    tree: &mut Node,
+    proposal: &mut Proposal,
+) -> Option<String> {
+    let existing = sequence(name)
+        .iter()
+        .try_fold(&*tree, |node, key| node.children.get(key));
+    if existing.and_then(|node| node.command.as_ref()) == Some(&alias.command) {
+        debug!("Skip alias which was already imported: {alias:?}");
+        return None;
+    }
+
+    let Some(keys) = std::iter::once(name.to_string())
+        .chain((2..10).map(|i| format!("{name}{i}")))
+        .find(|keys| is_free(tree, &sequence(keys)))
+    else {
+        proposal.warnings.push(format!(
+            "Skipped {:?}, because {}",
+            alias.name,
+            conflict(tree, name)
+        ));
+        return None;
+    };
+
+    let Some(mut text) = format_combo(&keys, &alias.command) else {
+        proposal.warnings.push(format!(
+            "Skipped {:?}: it can't be written as a leader combo",
+            alias.name
+        ));
+        return None;
+    };
+
+    if keys != name {
+        let message = format!(
+            "Renamed {:?} to {keys:?}, because {}",
+            alias.name,
+            conflict(tree, name)
+        );
+        text = format!("-- {message}\n{text}");
+        proposal.warnings.push(message);
+    }
+
+    tree.insert_command(&sequence(&keys), &alias.command);
+    proposal.count += 1;
+    Some(text)
+}
+
+/// Proposes combos for the aliases, avoiding any keys which are already taken, or which a combo
+/// that runs as soon as it's typed would make unreachable.
+///
+/// The aliases of a program which has several are grouped under a prefix key, which is the first
+/// letter of the program that no other group uses, and that leaves room for its combos (e.g. `g`
+/// for git, so `co` becomes `gco`, and `gs` stays as it is). Aliases with the same name and
+/// command are only imported once.
+pub fn propose(aliases: &[Alias], taken: &[(String, String)]) -> Proposal {
+    let mut proposal = Proposal::default();
+    let mut tree = Node::default();
+    let mut seen = BTreeSet::new();
+    let mut groups: BTreeMap<String, Vec<&Alias>> = BTreeMap::new();
+
+    for (keys, command) in taken {
+        if let Ok(sequence) = parse_key_sequence(keys) {
+            tree.insert_command(&sequence, command);
+        }
+    }
+
+    for alias in aliases {
+        if !seen.insert(alias) {
+            debug!("Skip duplicate alias: {alias:?}");
+            continue;
+        }
+
+        let program = alias.command.split_whitespace().next().unwrap_or_default();
+        groups.entry(program.to_string()).or_default().push(alias);
+    }
+
+    // Programs with a single alias are collected at the end, without a prefix.
+    let (single, multiple): (Vec<_>, Vec<_>) = groups.into_iter().partition(|(_, g)| g.len() == 1);
+    let mut prefixes = BTreeSet::new();
+
+    for (program, aliases) in multiple {
+        let prefix = program
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() && !prefixes.contains(c))
+            .find(|c| {
+                aliases
+                    .iter()
+                    .any(|alias| is_free(&tree, &sequence(&prefixed(*c, &alias.name))))
+            });
+        prefixes.extend(prefix);
+
+        let combos: Vec<String> = aliases
+            .into_iter()
+            .filter_map(|alias| {
+                let name = match prefix {
+                    Some(prefix) => prefixed(prefix, &alias.name),
+                    None => alias.name.clone(),
+                };
+                propose_combo(alias, &name, &mut tree, &mut proposal)
+            })
+            .collect();
+
+        if !combos.is_empty() {
+            let under = prefix.map(|p| format!(", under '{p}'")).unwrap_or_default();
+            proposal.text += &format!("-- {program}{under}\n{}\n\n", combos.join("\n"));
+        }
+    }
+
+    let combos: Vec<String> = single
+        .into_iter()
+        .flat_map(|(_, g)| g)
+        .filter_map(|alias| propose_combo(alias, &alias.name, &mut tree, &mut proposal))
+        .collect();
+    if !combos.is_empty() {
+        proposal.text += &format!("-- other\n{}\n", combos.join("\n"));
+    }
+
+    proposal.text = proposal.text.trim_end().to_string() + "\n";
+    proposal
+}
+
+#[test]
+fn test_parse_aliases() {
+    assert_eq!(
+        parse_zsh_aliases("gs='git status'\nl=ls\nq='echo '\\''hi'\\'''\nnl=$'a\\nb'"),
+        [
+            Alias::new("gs", "git status").unwrap(),
+            Alias::new("l", "ls").unwrap(),
+            Alias::new("q", "echo 'hi'").unwrap(),
+            Alias::new("nl", "a\nb").unwrap(),
+        ]
+    );
+    assert_eq!(
+        parse_git_aliases("alias.co checkout\nalias.up !git pull --rebase"),
+        [
+            Alias::new("co", "git checkout").unwrap(),
+            Alias::new("up", "git pull --rebase").unwrap(),
+        ]
+    );
+    assert_eq!(
+        parse_zsh_abbr("abbr \"gs\"=\"git status\"\nabbr -g gp='git push'"),
+        [
+            Alias::new("gs", "git status").unwrap(),
+            Alias::new("gp", "git push").unwrap(),
+        ]
+    );
+    assert_eq!(
+        parse_fish_abbr("abbr -a -- gs 'git status'\nabbr -a -U -- gco git\\ checkout"),
+        [
+            Alias::new("gs", "git status").unwrap(),
+            Alias::new("gco", "git checkout").unwrap(),
+        ]
+    );
+}
+
+#[test]
+fn test_propose() {
+    let aliases = [
+        Alias::new("gs", "git status").unwrap(),
+        Alias::new("gs", "git status").unwrap(),
+        Alias::new("co", "git checkout").unwrap(),
+        Alias::new("gc", "git commit").unwrap(),
+        Alias::new("gp", "git push").unwrap(),
+        Alias::new("ps", "go test ./...").unwrap(),
+        Alias::new("gb", "go build").unwrap(),
+        Alias::new("a=b", "echo").unwrap(),
+    ];
+    let taken = [
+        ("gcm".into(), "git commit -m".into()),
+        ("gp".into(), "git push".into()),
+    ];
+    let proposal = propose(&aliases, &taken);
+
+    // 'g' is taken by git, so go is grouped under 'o'.
+    assert_eq!(proposal.count, 6);
+    assert_eq!(
+        proposal.text,
+        r#"-- git, under 'g'
+gs = git status
+gco = git checkout
+-- Renamed "gc" to "gc2", because other combos start with "gc"
+gc2 = git commit
+
+-- go, under 'o'
+ops = go test ./...
+ogb = go build
+
+-- other
+a\=b = echo
+"#
+    );
+}
+
+#[test]
+fn test_propose_beneath_final_combo() {
+    let aliases = [
+        Alias::new("ps", "docker ps").unwrap(),
+        Alias::new("im", "docker images").unwrap(),
+        Alias::new("dl", "ls -l").unwrap(),
+    ];
+    // 'd' runs as soon as it's typed, so nothing can be added beneath it.
+    let taken = [("d".into(), "docker".into())];
+    let proposal = propose(&aliases, &taken);
+
+    assert_eq!(proposal.count, 2);
+    assert_eq!(
+        proposal.text,
+        "-- docker, under 'o'\nops = docker ps\noim = docker images\n"
+    );
+    assert_eq!(
+        proposal.warnings,
+        [r#"Skipped "dl", because "d" runs "docker" as soon as it's typed"#]
+    );
+}
@@ -0,0 +1,155 @@
+//! A cache of the parsed global config and the trees of leader-key combos.
+//!
+//! Every directory change in zsh emits the keybinds, so parsing the YAML each time adds visible
+//! lag on slow filesystems. The cache is invalidated whenever the modification time or size of a
+//! source file changes (including the fragments and included files), a fragment is added, or
+//! `blz` itself is updated.
+//!
+//! The cache is stored as JSON rather than in a binary encoding, to avoid a new dependency:
+//! `serde_json` is already used for the daemon's replies and the stats, and reading it is far
+//! cheaper than parsing the YAML, which is where the lag came from.
+
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use crate::{
+    CACHE_FILE_NAME, CONFIG_DIR, is_nushell,
+    nodes::Node,
+    yml::{Fragments, GlobalConfig},
+};
+
+/// Identifies the version of a source file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceStamp {
+    path: PathBuf,
+    modified_secs: u64,
+    modified_nanos: u32,
+    size: u64,
+}
+
+impl SourceStamp {
+    pub fn new(path: &Path) -> Option<SourceStamp> {
+        let metadata = std::fs::metadata(path).ok()?;
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+
+        Some(SourceStamp {
+            path: path.to_path_buf(),
+            modified_secs: modified.as_secs(),
+            modified_nanos: modified.subsec_nanos(),
+            size: metadata.len(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedConfig {
+    version: String,
+    /// The trees leave out the scripts which the shell has no version of.
+    #[serde(default)]
+    nushell: bool,
+    sources: Vec<SourceStamp>,
+    config: GlobalConfig,
+    leaders: BTreeMap<String, Node>,
+    fragments: Fragments,
+}
+
+#[derive(Serialize)]
+struct CachedConfigRef<'a> {
+    version: &'a str,
+    nushell: bool,
+    sources: &'a [SourceStamp],
+    config: &'a GlobalConfig,
+    leaders: &'a BTreeMap<String, Node>,
+    fragments: &'a Fragments,
+}
+
+fn cache_location() -> PathBuf {
+    CONFIG_DIR.join(CACHE_FILE_NAME)
+}
+
+/// Whether a config which was read from `known` is still up to date: none of those files has
+/// changed, and `sources` (the files which are found without parsing, see
+/// `configs::global_config_sources`) are all among them.
+pub fn is_fresh(known: &[SourceStamp], sources: &[SourceStamp]) -> bool {
+    sources.iter().all(|s| known.contains(s))
+        && known
+            .iter()
+            .all(|k| SourceStamp::new(&k.path).as_ref() == Some(k))
+}
+
+/// Reads the cached config, if it was compiled from the same versions of the sources.
+pub fn read(sources: &[SourceStamp]) -> Option<GlobalConfig> {
+    let content = std::fs::read(cache_location()).ok()?;
+    let cached: CachedConfig = serde_json::from_slice(&content).ok()?;
+
+    if cached.version != env!("CARGO_PKG_VERSION")
+        || cached.nushell != is_nushell()
+        || !is_fresh(&cached.sources, sources)
+    {
+        debug!("The config cache is stale");
+        return None;
+    }
+
+    let mut config = cached.config;
+    config.leader_trees = cached.leaders;
+    config.fragments = cached.fragments;
+    config.sources = cached.sources;
+
+    Some(config)
+}
+
+/// Builds the trees of all the leader keys which are valid. Invalid ones are left out, so that
+/// the error is reported when the leader key is triggered.
+pub fn compile(config: &mut GlobalConfig) {
+    let mut trees = BTreeMap::new();
+
+    if let Some(ref global) = config.global
+        && let Some(ref leaders) = global.leader_keys
+    {
+        for leader in leaders {
+            if let Ok(node) = Node::from_combos(&leader.combos) {
+                trees.insert(leader.sanitized_name(), node);
+            }
+        }
+    }
+
+    config.leader_trees = trees;
+}
+
+/// Writes the compiled config to the cache, with the files it was read from (see
+/// [`GlobalConfig::sources`]). The cache is only an optimisation, so failures are logged rather
+/// than returned.
+pub fn write(config: &GlobalConfig) {
+    let location = cache_location();
+    // Write to a temporary file first, so that other shells never read a partial cache.
+    let tmp = location.with_extension(format!("{}.tmp", std::process::id()));
+
+    let result = serde_json::to_vec(&CachedConfigRef {
+        version: env!("CARGO_PKG_VERSION"),
+        nushell: is_nushell(),
+        sources: &config.sources,
+        config,
+        leaders: &config.leader_trees,
+        fragments: &config.fragments,
+    })
+    .map_err(anyhow::Error::from)
+    .and_then(|content| Ok(std::fs::write(&tmp, content)?))
+    .and_then(|_| Ok(std::fs::rename(&tmp, &location)?));
+
+    match result {
+        Ok(_) => debug!("Wrote the config cache to {location:?}"),
+        Err(e) => {
+            info!("Failed to write the config cache: {e:?}");
+            let _ = std::fs::remove_file(&tmp);
+        }
+    }
+}
@@ -0,0 +1,127 @@
+//! 'blz capture-key', which shows what the terminal sends for each keypress, and can bind a key
+//! which has no name in the config (with `raw: true`).
+
+use anyhow::{Result, bail};
+use blaze_keys::{
+    conflicts,
+    edit::{self, Action},
+    shell::bindkey,
+    yml::GlobalConfig,
+};
+use colored::Colorize;
+use std::io::Write;
+use std::path::Path;
+use termion::input::TermReadEventsAndRaw;
+use termion::raw::IntoRawMode;
+
+use crate::cli::BindScope;
+use crate::config_edit;
+
+/// The bytes of Ctrl-c, which stops the capture.
+const INTERRUPT: &[u8] = &[0x03];
+
+/// The keys which are bound in the current directory, by their normalized sequence.
+fn bound_keys(global: &Option<GlobalConfig>) -> Vec<(String, String)> {
+    let keybinds = crate::resolve_for_cwd(global).unwrap_or_default();
+
+    conflicts::bound_keys(&keybinds, global)
+        .into_iter()
+        .map(|b| (b.sequence, b.key))
+        .collect()
+}
+
+/// Describes a keypress: its caret notation, its bytes, and its name in the config.
+fn describe(raw: &[u8], bound: &[(String, String)]) -> String {
+    let sequence = bindkey::caret_notation(raw);
+    let hex: Vec<String> = raw.iter().map(|b| format!("{b:02x}")).collect();
+    let name = match bindkey::config_key(&sequence) {
+        Some(name) => name,
+        None => "(raw only)".dimmed().to_string(),
+    };
+
+    let mut line = format!(
+        "{:<12} {:<20} {name:<12}",
+        sequence.bold(),
+        hex.join(" ").dimmed()
+    );
+
+    if let Some((_, key)) = bound.iter().find(|(s, _)| *s == sequence) {
+        line += &format!("  {}", format!("bound as {key:?}").green());
+    } else if raw.first().is_some_and(|b| *b >= 0x80) {
+        line += &format!(
+            "  {}",
+            "if this was an Alt key, the terminal isn't sending Alt as Escape".yellow()
+        );
+    }
+    line
+}
+
+/// Reads keypresses from the tty in raw mode, until `on_key` returns false or Ctrl-c is pressed.
+fn read_keys(mut on_key: impl FnMut(&[u8]) -> Result<bool>) -> Result<()> {
+    let _raw = std::io::stdout().into_raw_mode()?;
+
+    for event in termion::get_tty()?.events_and_raw() {
+        let (_, raw) = event?;
+
+        if raw == INTERRUPT || !on_key(&raw)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Shows the bytes which the terminal sends for each keypress, and what they're bound to.
+pub fn run(global: &Option<GlobalConfig>) -> Result<()> {
+    let bound = bound_keys(global);
+
+    println!("Press keys to see what your terminal sends. Press Ctrl-c to exit.");
+    println!(
+        "{}",
+        format!("{:<12} {:<20} {:<12}", "bindkey", "bytes", "config").dimmed()
+    );
+
+    read_keys(|raw| {
+        let mut stdout = std::io::stdout();
+        write!(stdout, "{}\r\n", describe(raw, &bound))?;
+        stdout.flush()?;
+        Ok(true)
+    })
+}
+
+/// Captures a single keypress, and binds it to the action.
+pub fn bind(config_file: &Path, action: Action, scope: &BindScope) -> Result<()> {
+    let bound = bound_keys(&crate::configs::parse_global_keybinds(config_file).transpose()?);
+
+    println!("Press the key to bind, or Ctrl-c to cancel.");
+
+    let mut captured = None;
+    read_keys(|raw| {
+        captured = Some(raw.to_vec());
+        Ok(false)
+    })?;
+
+    let Some(raw) = captured else {
+        bail!("Cancelled");
+    };
+    println!("{}", describe(&raw, &bound));
+
+    // A key with a name is more readable, and it works wherever the name is understood.
+    let sequence = bindkey::caret_notation(&raw);
+    let (key, is_raw) = match bindkey::config_key(&sequence) {
+        Some(name) => (name, false),
+        None => (sequence, true),
+    };
+
+    let scope = config_edit::scope(scope);
+    let file = config_edit::file_for(config_file, &scope);
+
+    config_edit::edit_scope_file(config_file, &scope, |config| {
+        edit::add_bind(config, &scope, &key, &action, is_raw)
+    })?;
+    println!("Bound {} in {file:?}.", key.green());
+
+    if scope == edit::Scope::Local {
+        println!("Run 'cd .' to apply the local keybinds.");
+    }
+    Ok(())
+}
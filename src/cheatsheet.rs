@@ -0,0 +1,459 @@
+//! Cheat sheets of the keybinds and leader combos, for printing or sharing.
+//!
+//! The combos of each leader key are grouped by their first key. Keys with a single combo beneath
+//! them are collected into one group, so the sheet doesn't fill up with tiny groups.
+
+use std::fmt::Write;
+
+use anyhow::Result;
+use box_drawing::light;
+
+use crate::{nodes::Node, resolve::ResolvedKeybind, vars::Vars, yml::GlobalConfig};
+
+const NEWLINE: &str = " ↵ ";
+const OTHER: &str = "Other";
+
+#[derive(Debug)]
+pub struct Sheet {
+    pub sections: Vec<Section>,
+}
+
+#[derive(Debug)]
+pub struct Section {
+    pub title: String,
+    pub subtitle: Option<String>,
+    pub groups: Vec<Group>,
+}
+
+#[derive(Debug)]
+pub struct Group {
+    pub title: String,
+    /// Whether the title is the first key of the combos in the group.
+    pub is_key: bool,
+    pub rows: Vec<Row>,
+}
+
+#[derive(Debug)]
+pub struct Row {
+    /// Each key which must be pressed, e.g. `["g", "<A-c>", "g"]`.
+    pub keys: Vec<String>,
+    pub command: String,
+    pub description: Option<String>,
+}
+
+impl Row {
+    fn keys(&self) -> String {
+        self.keys.concat()
+    }
+
+    /// The description, or the command if there isn't one.
+    fn label(&self) -> &str {
+        self.description.as_deref().unwrap_or(&self.command)
+    }
+}
+
+fn keybinds_section(keybinds: &[ResolvedKeybind]) -> Option<Section> {
+    let mut groups: Vec<Group> = vec![];
+
+    for resolved in keybinds {
+        let title = resolved.origin.to_string();
+        let command = resolved.keybind.target();
+        if command.is_empty() {
+            continue;
+        }
+        let row = Row {
+            keys: vec![resolved.keybind.key.clone()],
+            command,
+            description: None,
+        };
+
+        match groups.iter_mut().find(|g| g.title == title) {
+            Some(group) => group.rows.push(row),
+            None => groups.push(Group {
+                title,
+                is_key: false,
+                rows: vec![row],
+            }),
+        }
+    }
+
+    (!groups.is_empty()).then(|| Section {
+        title: "Keybinds".to_string(),
+        subtitle: None,
+        groups,
+    })
+}
+
+fn leader_groups(root: &Node) -> Vec<Group> {
+    let mut other = Group {
+        title: OTHER.to_string(),
+        is_key: false,
+        rows: vec![],
+    };
+    let mut groups: Vec<Group> = vec![];
+
+    for (path, node) in root.commands() {
+        if !node.is_available() {
+            continue;
+        }
+        let row = Row {
+            keys: path.iter().map(|k| k.str_short()).collect(),
+            command: node.command.clone().unwrap_or_default(),
+            description: node.description.clone(),
+        };
+
+        match groups.last_mut() {
+            Some(group) if group.title == row.keys[0] => group.rows.push(row),
+            _ => groups.push(Group {
+                title: row.keys[0].clone(),
+                is_key: true,
+                rows: vec![row],
+            }),
+        }
+    }
+
+    groups.retain_mut(|group| {
+        if group.rows.len() > 1 {
+            return true;
+        }
+        other.rows.append(&mut group.rows);
+        false
+    });
+
+    if !other.rows.is_empty() {
+        groups.insert(0, other);
+    }
+    groups
+}
+
+impl Sheet {
+    /// Builds the sheet from the given keybinds, and the combos of every leader key which can be
+    /// used with the given variables.
+    pub fn new(
+        keybinds: &[ResolvedKeybind],
+        global: &Option<GlobalConfig>,
+        vars: &Vars,
+    ) -> Result<Sheet> {
+        let mut sections: Vec<Section> = keybinds_section(keybinds).into_iter().collect();
+
+        let leaders = global
+            .as_ref()
+            .and_then(|g| g.global.as_ref())
+            .and_then(|g| g.leader_keys.as_ref());
+
+        for leader in leaders.into_iter().flatten() {
+            let root = Node::root(global, leader.sanitized_name(), vars)?;
+
+            sections.push(Section {
+                title: leader.name().to_string(),
+                subtitle: Some(format!(
+                    "exec: {}, abbr: {}",
+                    leader.exec_mode, leader.abbr_mode
+                )),
+                groups: leader_groups(&root),
+            });
+        }
+
+        Ok(Sheet { sections })
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::from("# Cheat sheet\n");
+
+        for section in &self.sections {
+            let _ = write!(out, "\n## {}\n", section.title);
+
+            if let Some(ref subtitle) = section.subtitle {
+                let _ = write!(out, "\n*{subtitle}*\n");
+            }
+
+            for group in &section.groups {
+                let title = match group.is_key {
+                    true => md_code(&group.title),
+                    false => group.title.clone(),
+                };
+                let _ = write!(
+                    out,
+                    "\n### {title}\n\n| Keys | Command | Description |\n| --- | --- | --- |\n"
+                );
+
+                for row in &group.rows {
+                    let _ = writeln!(
+                        out,
+                        "| {} | {} | {} |",
+                        md_cell(&md_code(&row.keys())),
+                        md_cell(&md_code(&row.command)),
+                        md_cell(row.description.as_deref().unwrap_or(""))
+                    );
+                }
+            }
+        }
+
+        out
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut out = String::from(
+            r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Cheat sheet</title>
+<style>
+  body { font-family: sans-serif; margin: 2em; color: #222; }
+  section { margin-bottom: 2em; }
+  .subtitle { color: #666; font-style: italic; }
+  table { border-collapse: collapse; margin-bottom: 1em; }
+  td, th { border: 1px solid #ccc; padding: 0.2em 0.6em; text-align: left; }
+  kbd { border: 1px solid #aaa; border-radius: 3px; padding: 0 0.3em; background: #f4f4f4; }
+</style>
+</head>
+<body>
+<h1>Cheat sheet</h1>
+"#,
+        );
+
+        for section in &self.sections {
+            let _ = writeln!(out, "<section>\n<h2>{}</h2>", html_escape(&section.title));
+
+            if let Some(ref subtitle) = section.subtitle {
+                let _ = writeln!(out, "<p class=\"subtitle\">{}</p>", html_escape(subtitle));
+            }
+
+            for group in &section.groups {
+                let _ = writeln!(
+                    out,
+                    "<h3>{}</h3>\n<table>\n<tr><th>Keys</th><th>Command</th><th>Description</th></tr>",
+                    html_escape(&group.title)
+                );
+
+                for row in &group.rows {
+                    let keys: String = row
+                        .keys
+                        .iter()
+                        .map(|k| format!("<kbd>{}</kbd>", html_escape(k)))
+                        .collect();
+
+                    let _ = writeln!(
+                        out,
+                        "<tr><td>{keys}</td><td><code>{}</code></td><td>{}</td></tr>",
+                        html_escape(&row.command.replace('\n', NEWLINE)),
+                        html_escape(row.description.as_deref().unwrap_or(""))
+                    );
+                }
+                out.push_str("</table>\n");
+            }
+            out.push_str("</section>\n");
+        }
+
+        out.push_str("</body>\n</html>\n");
+        out
+    }
+
+    /// A card with the keys drawn as keycaps, followed by the description of each combo.
+    pub fn to_svg(&self) -> String {
+        const LINE: usize = 26;
+        const CHAR: usize = 8;
+        const MARGIN: usize = 20;
+
+        let mut body = String::new();
+        let mut y = MARGIN;
+        let mut width = 0;
+
+        for section in &self.sections {
+            y += LINE;
+            let title = match section.subtitle {
+                Some(ref subtitle) => format!("{} ({subtitle})", section.title),
+                None => section.title.clone(),
+            };
+            let _ = writeln!(
+                body,
+                r#"<text x="{MARGIN}" y="{y}" class="title">{}</text>"#,
+                html_escape(&title)
+            );
+            width = width.max(title.chars().count() * (CHAR + 2));
+
+            for group in &section.groups {
+                y += LINE;
+                let _ = writeln!(
+                    body,
+                    r#"<text x="{MARGIN}" y="{y}" class="group">{}</text>"#,
+                    html_escape(&group.title)
+                );
+
+                for row in &group.rows {
+                    y += LINE;
+                    let mut x = MARGIN + 2 * CHAR;
+
+                    for key in &row.keys {
+                        let w = key.chars().count() * CHAR + 10;
+                        let _ = writeln!(
+                            body,
+                            r#"<rect x="{x}" y="{}" width="{w}" height="20" rx="4" class="key"/><text x="{}" y="{y}" class="cap">{}</text>"#,
+                            y - 15,
+                            x + 5,
+                            html_escape(key)
+                        );
+                        x += w + 4;
+                    }
+
+                    let label = row.label().replace('\n', NEWLINE);
+                    let _ = writeln!(
+                        body,
+                        r#"<text x="{}" y="{y}">{}</text>"#,
+                        x + CHAR,
+                        html_escape(&label)
+                    );
+                    width = width.max(x + CHAR + label.chars().count() * CHAR);
+                }
+            }
+            y += LINE / 2;
+        }
+
+        let (width, height) = (width + MARGIN, y + MARGIN);
+
+        format!(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">
+<style>
+  text {{ font-family: monospace; font-size: 13px; fill: #222; }}
+  .title {{ font-size: 16px; font-weight: bold; }}
+  .group {{ font-weight: bold; fill: #666; }}
+  .key {{ fill: #f4f4f4; stroke: #999; }}
+</style>
+<rect width="100%" height="100%" rx="10" fill="#fff" stroke="#ccc"/>
+{body}</svg>
+"##
+        )
+    }
+
+    /// A plain-text variant, with a box around each section.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+
+        for section in &self.sections {
+            let title = match section.subtitle {
+                Some(ref subtitle) => format!("{} ({subtitle})", section.title),
+                None => section.title.clone(),
+            };
+
+            let mut lines = vec![];
+            for group in &section.groups {
+                lines.push(group.title.clone());
+
+                let width = group.rows.iter().map(|r| len(&r.keys())).max();
+                for row in &group.rows {
+                    lines.push(format!(
+                        "  {:<width$}  {}",
+                        row.keys(),
+                        row.label().replace('\n', NEWLINE),
+                        width = width.unwrap_or(0)
+                    ));
+                }
+            }
+
+            let width = lines
+                .iter()
+                .map(|l| len(l))
+                .max()
+                .unwrap_or(0)
+                .max(len(&title) + 2);
+
+            let _ = writeln!(
+                out,
+                "{}{} {title} {}{}",
+                light::DOWN_RIGHT,
+                light::HORIZONTAL,
+                light::HORIZONTAL.repeat(width - len(&title) - 1),
+                light::DOWN_LEFT
+            );
+            for line in lines {
+                let _ = writeln!(
+                    out,
+                    "{} {line}{} {}",
+                    light::VERTICAL,
+                    " ".repeat(width - len(&line)),
+                    light::VERTICAL
+                );
+            }
+            let _ = writeln!(
+                out,
+                "{}{}{}\n",
+                light::UP_RIGHT,
+                light::HORIZONTAL.repeat(width + 2),
+                light::UP_LEFT
+            );
+        }
+
+        out
+    }
+}
+
+fn len(s: &str) -> usize {
+    s.chars().count()
+}
+
+/// Wraps the text in a code span, with a fence which is longer than any backticks in the text.
+fn md_code(text: &str) -> String {
+    let text = text.replace('\n', NEWLINE);
+    let longest = text
+        .split(|c| c != '`')
+        .map(|run| run.len())
+        .max()
+        .unwrap_or(0);
+    let fence = "`".repeat(longest + 1);
+
+    if longest > 0 {
+        format!("{fence} {text} {fence}")
+    } else {
+        format!("{fence}{text}{fence}")
+    }
+}
+
+fn md_cell(text: &str) -> String {
+    text.replace('|', r"\|").replace('\n', NEWLINE)
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[test]
+fn test_cheatsheet_groups() {
+    let global: GlobalConfig = serde_yml::from_str(
+        r#"
+global:
+  keybinds: []
+  leader_keys:
+    - name: Leader1
+      exec_mode: Ctrl-s
+      abbr_mode: Alt-s
+      combos:
+        - keys: gs
+          command: git status
+          description: Show the status
+        - keys: gl
+          command: git log
+        - keys: m
+          command: make -j`nproc`
+"#,
+    )
+    .unwrap();
+
+    let sheet = Sheet::new(&[], &Some(global), &Vars::new()).unwrap();
+    let groups: Vec<_> = sheet.sections[0]
+        .groups
+        .iter()
+        .map(|g| (g.title.as_str(), g.rows.len()))
+        .collect();
+    assert_eq!(groups, [("Other", 1), ("g", 2)]);
+
+    let markdown = sheet.to_markdown();
+    assert!(markdown.contains("| `` make -j`nproc` `` |"));
+    assert!(markdown.contains("| `gs` | `git status` | Show the status |"));
+
+    let text = sheet.to_text();
+    assert!(text.contains("│   gs  Show the status"));
+}
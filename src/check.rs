@@ -0,0 +1,118 @@
+//! 'blz check', which validates the configs and finds keys which are bound more than once.
+
+use anyhow::{Result, bail};
+use blaze_keys::{
+    conflicts::{self, Bound, Conflict},
+    keys, resolve,
+    shell::bindkey,
+};
+use colored::Colorize;
+use std::path::Path;
+
+use crate::configs;
+use crate::doctor::{fail, ok, section, warn};
+
+/// Reports the conflicts, without repeating any.
+fn report(conflicts: Vec<Conflict>, seen: &mut Vec<String>) -> usize {
+    let mut count = 0;
+
+    for conflict in conflicts {
+        let message = conflict.to_string();
+        if !seen.contains(&message) {
+            warn(&message);
+            seen.push(message);
+            count += 1;
+        }
+    }
+    count
+}
+
+/// The keys which replace the bindings in the output of `bindkey -L` (see
+/// [`crate::doctor::read_bindkey_listing`]).
+pub fn zsh_conflicts(bound: &[Bound], bindkey_file: Option<&str>) -> Result<Vec<Conflict>> {
+    let (listing, _) = crate::doctor::read_bindkey_listing(bindkey_file)?;
+
+    Ok(conflicts::zsh_conflicts(
+        bound,
+        &bindkey::parse_listing(&listing),
+    ))
+}
+
+pub fn run(config_file: &Path, bindkey_file: Option<&str>) -> Result<()> {
+    let mut errors = 0;
+
+    section("Configs");
+
+    let global = match configs::parse_global_keybinds(config_file).transpose() {
+        Ok(global) => global,
+        Err(e) => {
+            fail(format!("{e:#}"));
+            errors += 1;
+            None
+        }
+    };
+    match resolve::resolve_leaders(&global) {
+        Ok(leaders) => {
+            for leader in leaders {
+                ok(format!(
+                    "Leader key {:?} has {} combos",
+                    leader.name,
+                    leader.combos.len()
+                ));
+            }
+        }
+        Err(e) => {
+            fail(format!("{e:#}"));
+            errors += 1;
+        }
+    }
+
+    let keybinds = match crate::resolve_for_cwd(&global) {
+        Ok(keybinds) => keybinds,
+        Err(e) => {
+            fail(format!("{e:#}"));
+            errors += 1;
+            vec![]
+        }
+    };
+    for r in &keybinds {
+        if let Err(e) = keys::emit_keybind(&r.keybind, &|_, _, _, _| Ok(())) {
+            fail(format!("{} ({}): {e:#}", r.keybind.key, r.origin));
+            errors += 1;
+        }
+    }
+    if errors == 0 {
+        ok(format!(
+            "{} keybinds apply to this directory",
+            keybinds.len()
+        ));
+    }
+
+    section("Conflicts");
+
+    let mut seen = vec![];
+    let mut count = report(conflicts::profile_conflicts(&global), &mut seen);
+
+    let bound = conflicts::bound_keys(&keybinds, &global);
+    count += report(conflicts::layer_conflicts(&bound), &mut seen);
+
+    match zsh_conflicts(&bound, bindkey_file) {
+        Ok(found) => count += report(found, &mut seen),
+        Err(e) => warn(format!("Skipped the zsh bindings: {e:#}")),
+    }
+    if count == 0 {
+        ok("No conflicts");
+    }
+    if bindkey_file.is_none() {
+        println!(
+            "    {}",
+            "Only zsh's default bindings were checked. To check yours, run: bindkey -L | blz check --bindkey -"
+                .dimmed()
+        );
+    }
+
+    if errors > 0 {
+        bail!("Found {errors} errors in the configs");
+    }
+    Ok(())
+}
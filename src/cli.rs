@@ -1,4 +1,5 @@
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
@@ -27,6 +28,13 @@ pub struct Args {
     )]
     pub show_keybinds: bool,
 
+    #[clap(
+        long,
+        requires = "show_keybinds",
+        help = "With '-v', warn about keys which override other keybinds or zsh's default bindings."
+    )]
+    pub conflicts: bool,
+
     #[clap(
         short,
         long,
@@ -45,12 +53,143 @@ pub struct Args {
     )]
     pub print_template: Option<Option<String>>,
 
+    #[clap(
+        long,
+        global = true,
+        value_name = "DIR",
+        help = "Use the global config in DIR, rather than $BLZ_CONFIG_DIR or $XDG_CONFIG_HOME/blaze-keys."
+    )]
+    pub config: Option<PathBuf>,
+
     #[clap(subcommand)]
-    pub porcelain: Option<PorcelainWrapper>,
+    pub command: Option<Command>,
 }
 
 #[derive(Subcommand, Debug)]
-pub enum PorcelainWrapper {
+pub enum Command {
+    #[clap(about = "List the keybinds and leader combos which apply to the current directory.")]
+    List {
+        #[clap(long, value_enum, default_value_t = ListFormat::Human)]
+        format: ListFormat,
+    },
+    #[clap(about = "Export a cheat sheet of the keybinds and leader combos.")]
+    Export {
+        #[clap(long, value_enum, default_value_t = CheatsheetFormat::Markdown)]
+        format: CheatsheetFormat,
+
+        #[clap(
+            long,
+            help = "Include the keybinds of the profiles and local config which apply to the current directory."
+        )]
+        profiles: bool,
+    },
+    #[clap(about = "Import aliases or abbreviations from other tools as leader combos.")]
+    Import {
+        #[clap(value_enum)]
+        source: ImportSource,
+
+        #[clap(
+            help = "The file to read the definitions from ('-' for stdin). By default, they are read from the tool itself."
+        )]
+        file: Option<String>,
+
+        #[clap(
+            long,
+            default_value = "Leader1",
+            help = "The leader key to add the combos to."
+        )]
+        leader: String,
+
+        #[clap(
+            long,
+            help = "Add the combos to the global config, rather than printing them."
+        )]
+        write: bool,
+
+        #[clap(long, help = "The exec-mode trigger key, if the leader key is new.")]
+        exec_mode: Option<String>,
+
+        #[clap(long, help = "The abbr-mode trigger key, if the leader key is new.")]
+        abbr_mode: Option<String>,
+    },
+    #[clap(about = "Add a leader combo or keybind to the config, preserving its comments.")]
+    Add {
+        #[clap(subcommand)]
+        item: AddItem,
+    },
+    #[clap(about = "Remove a leader combo or keybind from the config, preserving its comments.")]
+    Remove {
+        #[clap(subcommand)]
+        item: RemoveItem,
+    },
+    #[clap(about = "Check the configs, and find keys which are bound more than once.")]
+    Check {
+        #[clap(
+            long,
+            value_name = "FILE",
+            help = "Check for conflicts with the output of 'bindkey -L' ('-' for stdin), rather than zsh's defaults."
+        )]
+        bindkey: Option<String>,
+    },
+    #[clap(about = "Diagnose why keybinds or leader keys aren't working.")]
+    Doctor {
+        #[clap(
+            long,
+            help = "Show the bytes which the terminal sends for each keypress."
+        )]
+        keys: bool,
+
+        #[clap(
+            long,
+            value_name = "FILE",
+            help = "Check for conflicts with the output of 'bindkey -L' ('-' for stdin), rather than zsh's defaults."
+        )]
+        bindkey: Option<String>,
+    },
+    #[clap(
+        about = "Show which leader combos and keybinds are used most and least, and which never."
+    )]
+    Stats {
+        #[clap(
+            long,
+            conflicts_with = "disable",
+            help = "Start recording the uses of combos and keybinds. They're only stored locally, in the state dir."
+        )]
+        enable: bool,
+
+        #[clap(long, help = "Stop recording, keeping what was recorded.")]
+        disable: bool,
+
+        #[clap(long, help = "Forget what was recorded.")]
+        reset: bool,
+
+        #[clap(
+            long,
+            default_value_t = 10,
+            help = "The number of combos to show in each list."
+        )]
+        top: usize,
+    },
+    #[clap(about = "Show the escape sequences which the terminal sends for keypresses.")]
+    CaptureKey {
+        #[clap(
+            long,
+            value_name = "COMMAND",
+            help = "Bind the next key which is pressed to a command, using 'raw: true' if the key has no name."
+        )]
+        bind: Option<String>,
+
+        #[clap(
+            long,
+            requires = "bind",
+            help = "Bind the key to a zle widget, rather than a command."
+        )]
+        zle: bool,
+
+        #[clap(flatten)]
+        scope: BindScope,
+    },
+    #[clap(about = "Commands which are used by the shell integration.")]
     Porcelain {
         #[clap(subcommand)]
         inner: Porcelain,
@@ -60,6 +199,80 @@ pub enum PorcelainWrapper {
     },
 }
 
+/// Where a keybind is edited.
+#[derive(clap::Args, Debug)]
+pub struct BindScope {
+    #[clap(long, help = "Edit the keybinds of a profile in the global config.")]
+    pub profile: Option<String>,
+
+    #[clap(
+        long,
+        conflicts_with = "profile",
+        help = "Edit the local config in the current directory."
+    )]
+    pub local: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum AddItem {
+    #[clap(about = "Add a combo to a leader key.")]
+    Combo {
+        leader: String,
+        keys: String,
+        command: String,
+    },
+    #[clap(about = "Add a keybind.")]
+    Bind {
+        key: String,
+        command: String,
+
+        #[clap(long, help = "Bind the key to a zle widget, rather than a command.")]
+        zle: bool,
+
+        #[clap(flatten)]
+        scope: BindScope,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum RemoveItem {
+    #[clap(about = "Remove a combo from a leader key.")]
+    Combo { leader: String, keys: String },
+    #[clap(about = "Remove a keybind.")]
+    Bind {
+        key: String,
+
+        #[clap(flatten)]
+        scope: BindScope,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum ListFormat {
+    Human,
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum CheatsheetFormat {
+    Markdown,
+    Html,
+    Svg,
+    Text,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum ImportSource {
+    /// The output of `zsh -ic alias`.
+    Zsh,
+    /// The output of `git config --get-regexp alias`.
+    Git,
+    /// The user abbreviations file of zsh-abbr.
+    ZshAbbr,
+    /// The output of `abbr --show` in fish.
+    Fish,
+}
+
 #[allow(non_camel_case_types)]
 #[derive(Subcommand, Debug)]
 pub enum Porcelain {
@@ -72,6 +285,11 @@ pub enum Porcelain {
 
         #[clap(long)]
         abbr: bool,
+
+        /// Read the current command line, which some combos transform, from the tmpfile. It's
+        /// passed through the file so that other users can't see it in the list of processes.
+        #[clap(long)]
+        read_buffer: bool,
     },
     #[clap(about = "Prints the state of the leader keys.")]
     print_leader_state,
@@ -81,21 +299,31 @@ pub enum Porcelain {
     generate_nu_source,
     #[clap(about = "Print the path to the nu sources file.")]
     print_nu_source_path,
+    #[clap(
+        about = "Run the daemon, which keeps the parsed config in memory to answer queries from the shell hooks."
+    )]
+    daemon,
     #[clap(about = "Emit top-level keybinds.")]
-    blat,
+    blat {
+        #[clap(
+            long,
+            help = "The state of the keybinds which were previously applied; only the keybinds which differ will be emitted."
+        )]
+        applied: Option<String>,
+    },
+    #[clap(about = "Record a use of a keybind, if the stats are enabled.")]
+    record_keybind { key: String },
 }
 
 #[macro_export]
 macro_rules! porcelain_get {
     ($args:tt, $which:pat => $then:expr) => {
         $args
-            .porcelain
+            .command
             .as_ref()
             .map(|it| match it {
-                PorcelainWrapper::Porcelain { inner, .. } => match inner {
-                    $which => Some($then),
-                    _ => None,
-                },
+                Command::Porcelain { inner: $which, .. } => Some($then),
+                _ => None,
             })
             .flatten()
     };
@@ -104,10 +332,11 @@ macro_rules! porcelain_get {
 #[macro_export]
 macro_rules! porcelain_get_bool {
     ($args:tt, $which:pat) => {
-        $args.porcelain.as_ref().is_some_and(|it| match it {
-            PorcelainWrapper::Porcelain { inner, .. } => {
+        $args.command.as_ref().is_some_and(|it| match it {
+            Command::Porcelain { inner, .. } => {
                 matches!(inner, $which)
             }
+            _ => false,
         })
     };
 }
@@ -0,0 +1,138 @@
+//! 'blz add' and 'blz remove', which edit the config without losing its comments.
+
+use anyhow::{Context, Result};
+use blaze_keys::{
+    CONFIG_FILE_NAME,
+    edit::{self, Action, Scope},
+};
+use colored::Colorize;
+use std::path::{Path, PathBuf};
+
+use crate::cli::{AddItem, BindScope, RemoveItem};
+use crate::configs;
+
+pub(crate) fn scope(scope: &BindScope) -> Scope {
+    match (&scope.profile, scope.local) {
+        (Some(profile), _) => Scope::Profile(profile.clone()),
+        (None, true) => Scope::Local,
+        (None, false) => Scope::Global,
+    }
+}
+
+pub(crate) fn file_for<'a>(config_file: &'a Path, scope: &Scope) -> std::borrow::Cow<'a, Path> {
+    match scope {
+        Scope::Local => PathBuf::from(CONFIG_FILE_NAME).into(),
+        _ => config_file.into(),
+    }
+}
+
+/// Applies an edit to the file, keeping a backup of the original.
+pub(crate) fn edit_file(file: &Path, edit: impl FnOnce(&str) -> Result<String>) -> Result<()> {
+    let config = match file.exists() {
+        true => {
+            std::fs::read_to_string(file).with_context(|| format!("Failed to read {file:?}"))?
+        }
+        false => String::new(),
+    };
+    let edited = edit(&config)?;
+
+    if file.exists() {
+        let mut backup = file.as_os_str().to_owned();
+        backup.push(".bak");
+        std::fs::copy(file, &backup)
+            .with_context(|| format!("Failed to back up {file:?} to {backup:?}"))?;
+    } else if let Some(dir) = file.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(file, edited).with_context(|| format!("Failed to write {file:?}"))?;
+
+    Ok(())
+}
+
+/// Applies an edit to the global config, checking that it's still valid once it's merged with the
+/// files it includes and the fragments, as when it's loaded.
+pub(crate) fn edit_global_file(
+    file: &Path,
+    edit: impl FnOnce(&str) -> Result<String>,
+) -> Result<()> {
+    edit_file(file, |config| {
+        let edited = edit(config)?;
+        configs::merge_global_config(file, &edited)
+            .context("The edited config would be invalid")?;
+        Ok(edited)
+    })
+}
+
+/// Applies an edit to the file of the scope (see [`file_for`]).
+pub(crate) fn edit_scope_file(
+    config_file: &Path,
+    scope: &Scope,
+    edit: impl FnOnce(&str) -> Result<String>,
+) -> Result<()> {
+    match scope {
+        Scope::Local => edit_file(&file_for(config_file, scope), edit),
+        _ => edit_global_file(config_file, edit),
+    }
+}
+
+pub fn add(config_file: &Path, item: &AddItem) -> Result<()> {
+    match item {
+        AddItem::Combo {
+            leader,
+            keys,
+            command,
+        } => {
+            edit_global_file(config_file, |config| {
+                edit::add_combo(config, leader, keys, command)
+            })?;
+            println!("Added {} to the leader key {leader:?}.", keys.green());
+        }
+        AddItem::Bind {
+            key,
+            command,
+            zle,
+            scope: bind_scope,
+        } => {
+            let scope = scope(bind_scope);
+            let action = match zle {
+                true => Action::Zle(command.clone()),
+                false => Action::Command(command.clone()),
+            };
+            let file = file_for(config_file, &scope);
+
+            edit_scope_file(config_file, &scope, |config| {
+                edit::add_bind(config, &scope, key, &action, false)
+            })?;
+            println!("Bound {} in {file:?}.", key.green());
+
+            if scope == Scope::Local {
+                println!("Run 'cd .' to apply the local keybinds.");
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn remove(config_file: &Path, item: &RemoveItem) -> Result<()> {
+    match item {
+        RemoveItem::Combo { leader, keys } => {
+            edit_global_file(config_file, |config| {
+                edit::remove_combo(config, leader, keys)
+            })?;
+            println!("Removed {} from the leader key {leader:?}.", keys.red());
+        }
+        RemoveItem::Bind {
+            key,
+            scope: bind_scope,
+        } => {
+            let scope = scope(bind_scope);
+            let file = file_for(config_file, &scope);
+
+            edit_scope_file(config_file, &scope, |config| {
+                edit::remove_bind(config, &scope, key)
+            })?;
+            println!("Unbound {} in {file:?}.", key.red());
+        }
+    }
+    Ok(())
+}
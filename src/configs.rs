@@ -1,7 +1,8 @@
-use anyhow::Result;
-use anyhow::anyhow;
+use anyhow::{Context, Result, anyhow, bail};
 use blaze_keys::{
     CONFIG_FILE_NAME,
+    cache::{self, SourceStamp},
+    resolve,
     yml::{GlobalConfig, LocalConfig},
 };
 use colored::Colorize;
@@ -57,6 +58,166 @@ pub fn select_template_interactive(intro: &str) -> Result<&'static str> {
     Ok(template)
 }
 
+/// The directory beside the global config whose fragments are merged into it.
+const FRAGMENTS_DIR: &str = "conf.d";
+
+/// The fragments of the global config, in lexical order.
+fn fragment_files(path: &Path) -> Vec<PathBuf> {
+    let dir = path.parent().unwrap_or(Path::new(".")).join(FRAGMENTS_DIR);
+
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|file| file.extension().is_some_and(|e| e == "yml" || e == "yaml"))
+        .collect();
+    files.sort();
+    files
+}
+
+/// The files which the global config is read from, which are used to invalidate caches. These
+/// are found without parsing the config, so the included files aren't among them (see
+/// [`cache::is_fresh`]).
+pub fn global_config_sources(path: &Path) -> Vec<SourceStamp> {
+    std::iter::once(path.to_path_buf())
+        .chain(fragment_files(path))
+        .filter_map(|file| SourceStamp::new(&file))
+        .collect()
+}
+
+/// Reads a file of the global config into `config`, after the files which it includes.
+///
+/// `fragment` is false for the main config file. `stack` holds the canonical paths of the files
+/// which are being read, to detect a file which includes itself.
+fn read_config_file(
+    path: &Path,
+    fragment: bool,
+    config: &mut GlobalConfig,
+    stack: &mut Vec<PathBuf>,
+) -> Result<()> {
+    // The same file can be reached by different paths, e.g. 'a/../b.yml' and 'b.yml'.
+    let canonical =
+        std::fs::canonicalize(path).with_context(|| format!("Failed to read {path:?}"))?;
+
+    if stack.contains(&canonical) {
+        bail!("{path:?} includes itself, via {:?}", stack.last().unwrap());
+    }
+    if config
+        .sources
+        .iter()
+        .any(|s| std::fs::canonicalize(s.path()).is_ok_and(|p| p == canonical))
+    {
+        info!("Skip {path:?}, which was already included");
+        return Ok(());
+    }
+
+    info!("Read global config file: {path:?}");
+    let content =
+        std::fs::read_to_string(path).with_context(|| format!("Failed to read {path:?}"))?;
+    merge_config_text(path, canonical, &content, fragment, config, stack)
+}
+
+/// Merges the text of a file of the global config into `config`, after the files which it
+/// includes (see [`read_config_file`]).
+fn merge_config_text(
+    path: &Path,
+    canonical: PathBuf,
+    content: &str,
+    fragment: bool,
+    config: &mut GlobalConfig,
+    stack: &mut Vec<PathBuf>,
+) -> Result<()> {
+    let mut parsed: GlobalConfig =
+        serde_yml::from_str(content).with_context(|| format!("Failed to parse {path:?}"))?;
+
+    stack.push(canonical);
+    for include in parsed.include.take().into_iter().flatten() {
+        let expanded = shellexpand::full(&include)
+            .with_context(|| format!("Failed to expand include {include:?} in {path:?}"))?;
+        // Relative paths are relative to the including file.
+        let file = path
+            .parent()
+            .unwrap_or(Path::new("."))
+            .join(expanded.as_ref());
+
+        read_config_file(&file, true, config, stack)
+            .with_context(|| format!("Failed to include {include:?} in {path:?}"))?;
+    }
+    stack.pop();
+
+    config.sources.extend(SourceStamp::new(path));
+    config
+        .merge(parsed, fragment.then_some(path))
+        .with_context(|| format!("Failed to merge {path:?}"))
+}
+
+/// Merges the main config file, given its text, with the files it includes and the fragments in
+/// `conf.d`, in order.
+pub fn merge_global_config(path: &Path, content: &str) -> Result<GlobalConfig> {
+    let mut config = GlobalConfig::default();
+    // The main file doesn't exist yet when the first combos are added.
+    let canonical = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+
+    merge_config_text(path, canonical, content, false, &mut config, &mut vec![])?;
+    for file in fragment_files(path) {
+        read_config_file(&file, true, &mut config, &mut vec![])?;
+    }
+
+    resolve::validate_profiles(&config).with_context(|| format!("Invalid profiles in {path:?}"))?;
+    Ok(config)
+}
+
+/// Reads the global config, then the fragments in `conf.d`, merging each (and the files they
+/// include) in order.
+fn read_global_config(path: &Path) -> Result<GlobalConfig> {
+    let content =
+        std::fs::read_to_string(path).with_context(|| format!("Failed to read {path:?}"))?;
+    merge_global_config(path, &content)
+}
+
+#[test]
+fn test_include_cycle() {
+    let root = std::env::temp_dir().join(format!("blz-test-include-{}", std::process::id()));
+    let file = |name: &str, content: &str| {
+        let path = root.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, content).unwrap();
+        path
+    };
+
+    // The paths of the includes grow ('cfg/../cfg2/../cfg2/c.yml'), but the files are the same.
+    let main = file("cfg/.blz.yml", "include: [../cfg2/b.yml]\n");
+    file("cfg2/b.yml", "include: [../cfg2/c.yml]\n");
+    file("cfg2/c.yml", "include: [../cfg2/b.yml]\n");
+
+    let error = read_global_config(&main).unwrap_err();
+    let _ = std::fs::remove_dir_all(&root);
+
+    assert!(format!("{error:#}").contains("includes itself"));
+    assert!(error.chain().count() < 10);
+}
+
+#[test]
+fn test_merge_edited_config() {
+    let root = std::env::temp_dir().join(format!("blz-test-merge-{}", std::process::id()));
+    std::fs::create_dir_all(root.join(FRAGMENTS_DIR)).unwrap();
+    std::fs::write(
+        root.join(FRAGMENTS_DIR).join("base.yml"),
+        "profiles:\n  - name: Base\n    conditions: []\n",
+    )
+    .unwrap();
+
+    // The profile extends one of a fragment, so the main file is only valid once merged.
+    let main = root.join(".blz.yml");
+    let edited = "profiles:\n  - name: Rust\n    extends: [Base]\n    conditions: []\n";
+    let result = merge_global_config(&main, edited);
+    let _ = std::fs::remove_dir_all(&root);
+
+    assert!(blaze_keys::edit::validate(edited).is_ok());
+    assert_eq!(result.unwrap().profiles.unwrap().len(), 2);
+}
+
 pub fn parse_global_keybinds<T>(path: T) -> Option<Result<GlobalConfig>>
 where
     T: AsRef<Path>,
@@ -68,13 +229,18 @@ where
         return None;
     }
 
-    if let Ok(c) = std::fs::read_to_string(path) {
-        match serde_yml::from_str(&c) {
-            Ok(init_conf) => Some(Ok(init_conf)),
-            Err(e) => Some(Err(anyhow::Error::from(e))),
+    if let Some(config) = cache::read(&global_config_sources(path)) {
+        info!("Loaded global config from the cache");
+        return Some(Ok(config));
+    }
+
+    match read_global_config(path) {
+        Ok(mut config) => {
+            cache::compile(&mut config);
+            cache::write(&config);
+            Some(Ok(config))
         }
-    } else {
-        Some(Err(anyhow::anyhow!("Failed to read global config file")))
+        Err(e) => Some(Err(e)),
     }
 }
 
@@ -121,7 +287,7 @@ pub fn create_global_config_interactive(config_file: &PathBuf) -> Result<()> {
     Ok(())
 }
 
-pub fn edit_config_file(config_dir: &str, config_file: &PathBuf) -> Result<()> {
+pub fn edit_config_file(config_dir: &Path, config_file: &PathBuf) -> Result<()> {
     std::fs::create_dir_all(config_dir)?;
 
     if !config_file.exists() {
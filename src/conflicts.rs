@@ -0,0 +1,306 @@
+//! Finds keys which are bound more than once: by several layers of the config (global, profiles
+//! and local), or by blz and by zsh itself.
+
+use std::{collections::BTreeMap, fmt::Display};
+
+use crate::{
+    keys,
+    resolve::{self, Origin, ResolvedKeybind},
+    shell::bindkey::{self, Binding},
+    yml::{GlobalConfig, Keybind},
+};
+
+/// What binds a key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Keybind(Origin),
+    /// The trigger of a leader key.
+    Leader(String),
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Keybind(origin) => write!(f, "{origin}"),
+            Source::Leader(name) => write!(f, "leader key '{name}'"),
+        }
+    }
+}
+
+/// A key which blz binds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bound {
+    pub key: String,
+    /// The key sequence, in normalized caret notation (see [`bindkey`]).
+    pub sequence: String,
+    pub source: Source,
+    /// What the key does: the command, zle widget or steps, or None for a leader key.
+    pub target: Option<String>,
+}
+
+impl Bound {
+    /// Whether an existing zsh binding was made by blz for this key.
+    fn made(&self, binding: &Binding) -> bool {
+        match &self.target {
+            Some(_) if binding.target == keys::widget_name(&self.sequence) => true,
+            Some(target) if binding.is_string => binding.target == format!("{target}^M"),
+            Some(target) => binding.target == *target,
+            None => binding.target.starts_with("_zsh_leader"),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match &self.target {
+            Some(target) => format!("{target:?} ({})", self.source),
+            None => self.source.to_string(),
+        }
+    }
+}
+
+/// The keys which blz binds, in the order they're applied, including the keybinds which were
+/// shadowed by another layer.
+pub fn bound_keys(keybinds: &[ResolvedKeybind], global: &Option<GlobalConfig>) -> Vec<Bound> {
+    let all = keybinds
+        .iter()
+        .flat_map(|r| r.shadowed.iter().chain(std::iter::once(r)));
+
+    let keybinds = all.filter_map(|r| {
+        let keybind = &r.keybind;
+        let sequence = match keybind.raw {
+            Some(true) => bindkey::normalize(&keybind.key),
+            _ => bindkey::key_sequence(&keybind.key)?,
+        };
+
+        Some(Bound {
+            key: keybind.key.clone(),
+            sequence,
+            source: Source::Keybind(r.origin.clone()),
+            target: keybind
+                .command
+                .clone()
+                .or_else(|| keybind.zle.clone())
+                .or_else(|| Some(keybind.target()).filter(|t| !t.is_empty())),
+        })
+    });
+
+    let leaders = global
+        .iter()
+        .flat_map(|g| g.global.iter())
+        .flat_map(|g| g.leader_keys.iter().flatten())
+        .flat_map(|leader| {
+            [&leader.exec_mode, &leader.abbr_mode]
+                .into_iter()
+                .filter_map(|key| {
+                    Some(Bound {
+                        key: key.clone(),
+                        sequence: bindkey::key_sequence(key)?,
+                        source: Source::Leader(leader.name().to_string()),
+                        target: None,
+                    })
+                })
+        });
+
+    keybinds.chain(leaders).collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Conflict {
+    /// A key which is bound differently by several layers, in the order they're applied.
+    Layers(Vec<Bound>),
+    /// A key which replaces a binding which zsh already has.
+    Zsh { bound: Bound, existing: Binding },
+}
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Conflict::Layers(bound) => {
+                let describe = |bound: &[Bound]| -> String {
+                    let described: Vec<String> = bound.iter().map(Bound::describe).collect();
+                    described.join(" and ")
+                };
+                let (leaders, keybinds): (Vec<Bound>, Vec<Bound>) = bound
+                    .iter()
+                    .cloned()
+                    .partition(|b| matches!(b.source, Source::Leader(_)));
+
+                match keybinds.split_last() {
+                    Some((last, rest)) if leaders.is_empty() => write!(
+                        f,
+                        "{} is bound to {}, which overrides {}",
+                        last.key,
+                        last.describe(),
+                        describe(rest)
+                    ),
+                    _ => write!(
+                        f,
+                        "{} triggers {}, and is also bound to {}",
+                        leaders.first().unwrap_or(&bound[0]).key,
+                        describe(&leaders),
+                        describe(&keybinds)
+                    ),
+                }
+            }
+            Conflict::Zsh { bound, existing } => write!(
+                f,
+                "{} ({}) replaces the zsh binding to {:?}",
+                bound.key, bound.source, existing.target
+            ),
+        }
+    }
+}
+
+/// Finds keys which are bound differently by several layers.
+pub fn layer_conflicts(bound: &[Bound]) -> Vec<Conflict> {
+    let mut by_sequence: BTreeMap<&str, Vec<&Bound>> = BTreeMap::new();
+    for b in bound {
+        by_sequence.entry(&b.sequence).or_default().push(b);
+    }
+
+    by_sequence
+        .into_values()
+        .filter(|group| {
+            // The same command in several layers is harmless.
+            group.len() > 1
+                && group
+                    .iter()
+                    .any(|b| b.target.is_none() || b.target != group[0].target)
+        })
+        .map(|group| Conflict::Layers(group.into_iter().cloned().collect()))
+        .collect()
+}
+
+/// Finds keys which each profile binds differently to the global config, wherever the profile
+/// applies.
+pub fn profile_conflicts(global: &Option<GlobalConfig>) -> Vec<Conflict> {
+    let with_origin = |keybinds: &[Keybind], origin: Origin| -> Vec<ResolvedKeybind> {
+        keybinds
+            .iter()
+            .map(|keybind| ResolvedKeybind {
+                keybind: keybind.clone(),
+                origin: origin.clone(),
+                shadowed: vec![],
+            })
+            .collect()
+    };
+    let global_keybinds = with_origin(
+        global
+            .as_ref()
+            .and_then(|g| g.global.as_ref())
+            .map_or(&[], |g| g.keybinds.as_slice()),
+        Origin::Global,
+    );
+
+    global
+        .iter()
+        .flat_map(|g| g.profiles.iter().flatten().map(move |profile| (g, profile)))
+        .flat_map(|(g, profile)| {
+            let origin = Origin::Profile {
+                name: profile.name.clone(),
+            };
+            let mut keybinds = global_keybinds.clone();
+
+            // A profile which extends others is checked with their keybinds beneath it.
+            for p in resolve::expand_profile(g, profile).unwrap_or_default() {
+                let p_origin = match p.name == profile.name {
+                    true => origin.clone(),
+                    false => Origin::Extended {
+                        name: p.name.clone(),
+                        by: Box::new(origin.clone()),
+                    },
+                };
+                keybinds.extend(with_origin(
+                    p.keybinds.as_deref().unwrap_or_default(),
+                    p_origin,
+                ));
+            }
+            layer_conflicts(&bound_keys(&keybinds, &None))
+        })
+        .collect()
+}
+
+/// Finds keys which replace a significant binding in the output of `bindkey -L`, ignoring the
+/// bindings which blz made itself. Only the last binding of each key is compared, since those it
+/// shadows never replace zsh's.
+pub fn zsh_conflicts(bound: &[Bound], existing: &[Binding]) -> Vec<Conflict> {
+    bound
+        .iter()
+        .enumerate()
+        .filter(|(i, b)| !bound[i + 1..].iter().any(|o| o.sequence == b.sequence))
+        .flat_map(|(_, b)| {
+            existing
+                .iter()
+                .filter(|e| e.key == b.sequence && bindkey::is_significant(e))
+                .filter(|e| !bound.iter().any(|o| o.sequence == e.key && o.made(e)))
+                .map(|e| Conflict::Zsh {
+                    bound: b.clone(),
+                    existing: e.clone(),
+                })
+        })
+        .collect()
+}
+
+#[test]
+fn test_conflicts() {
+    use crate::resolve::resolve_keybinds;
+    use std::path::Path;
+
+    let global: GlobalConfig = serde_yml::from_str(
+        r#"
+global:
+  keybinds:
+    - key: Ctrl-r
+      command: make
+    - key: Alt-b
+      command: cargo build
+  leader_keys:
+    - name: L
+      exec_mode: Alt-b
+      abbr_mode: Alt-c
+      combos: "x = exit"
+"#,
+    )
+    .unwrap();
+    let local: crate::yml::LocalConfig = serde_yml::from_str(
+        r#"
+keybinds:
+  - key: Ctrl-r
+    command: make test
+  - key: "^[c"
+    zle: push-line
+    raw: true
+"#,
+    )
+    .unwrap();
+
+    let global = Some(global);
+    let keybinds = resolve_keybinds(&global, Some((Path::new(".blz.yml"), &local))).unwrap();
+    let bound = bound_keys(&keybinds, &global);
+
+    let conflicts: Vec<String> = layer_conflicts(&bound)
+        .iter()
+        .map(|c| c.to_string())
+        .collect();
+    assert_eq!(
+        conflicts,
+        [
+            r#"Ctrl-r is bound to "make test" (local (.blz.yml)), which overrides "make" (global)"#,
+            r#"Alt-b triggers leader key 'L', and is also bound to "cargo build" (global)"#,
+            r#"Alt-c triggers leader key 'L', and is also bound to "push-line" (local (.blz.yml))"#,
+        ]
+    );
+
+    let existing = bindkey::parse_listing(
+        "bindkey \"^R\" history-incremental-search-backward\nbindkey -s \"^[b\" \"cargo build^M\"",
+    );
+    let conflicts: Vec<String> = zsh_conflicts(&bound, &existing)
+        .iter()
+        .map(|c| c.to_string())
+        .collect();
+    assert_eq!(
+        conflicts,
+        [
+            r#"Ctrl-r (local (.blz.yml)) replaces the zsh binding to "history-incremental-search-backward""#,
+        ]
+    );
+}
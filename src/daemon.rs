@@ -0,0 +1,214 @@
+//! An optional long-running process which keeps the parsed config in memory, so that the shell
+//! hooks don't need to parse the config again for every directory change.
+//!
+//! Each connection sends one request line; the daemon replies and closes the connection.
+//!
+//! - `binds\t<dir>\t<config state>\t<applied state>` replies with the shell code which applies the
+//!   keybinds for the directory, as 'blz porcelain blat' would.
+//! - `leader\t<name>\t<dir>` replies with the tree of combos for the leader key in the directory,
+//!   and the state of the config, as JSON.
+//!
+//! The fields are separated by tabs, so the clients don't use the daemon in a directory whose
+//! path contains a tab or a newline.
+//!
+//! The config files are checked for changes on every request, so the replies are never stale.
+//! The daemon's environment isn't the shell's, so placeholders of environment variables are left
+//! for the shell, or for the leader key's process, to expand.
+
+use anyhow::{Result, anyhow};
+use blaze_keys::{
+    cache::{self, SourceStamp},
+    daemon_socket_dir, daemon_socket_path, is_private_dir, is_trusted_socket,
+    nodes::Node,
+    shell,
+    shell::zsh_hook,
+    vars,
+    yml::GlobalConfig,
+};
+use log::{debug, info, warn};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::configs;
+
+struct Daemon {
+    config_file: PathBuf,
+    sources: Vec<SourceStamp>,
+    config: Result<Option<GlobalConfig>, String>,
+    /// See [`shell::config_state`].
+    state: String,
+}
+
+impl Daemon {
+    fn new(config_file: PathBuf) -> Daemon {
+        let mut daemon = Daemon {
+            config_file,
+            sources: vec![],
+            config: Ok(None),
+            state: String::new(),
+        };
+        daemon.load();
+        daemon
+    }
+
+    fn load(&mut self) {
+        self.config = configs::parse_global_keybinds(&self.config_file)
+            .transpose()
+            .map_err(|e| format!("{e:#}"));
+        self.sources = match self.config {
+            Ok(Some(ref config)) => config.sources.clone(),
+            _ => configs::global_config_sources(&self.config_file),
+        };
+        self.state = match self.config {
+            Ok(ref config) => shell::config_state(config),
+            Err(_) => String::new(),
+        };
+
+        info!("Loaded the global config from {:?}", self.config_file);
+    }
+
+    /// Reloads the config if any of its files have changed.
+    fn refresh(&mut self) {
+        if !cache::is_fresh(
+            &self.sources,
+            &configs::global_config_sources(&self.config_file),
+        ) {
+            self.load();
+        }
+    }
+
+    fn handle(&mut self, stream: UnixStream) -> Result<()> {
+        stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+
+        let mut request = String::new();
+        BufReader::new(&stream).read_line(&mut request)?;
+        let request = request.trim_end_matches('\n');
+        debug!("Request: {request:?}");
+
+        self.refresh();
+
+        let reply = match request.split('\t').collect::<Vec<_>>().as_slice() {
+            ["binds", dir, leader_state, applied] => self.binds(dir, leader_state, applied),
+            ["leader", name, dir] => self.leader(name, dir),
+            _ => return Err(anyhow!("Invalid request: {request:?}")),
+        };
+
+        (&stream).write_all(reply.as_bytes())?;
+        Ok(())
+    }
+
+    fn binds(&self, dir: &str, state: &str, applied: &str) -> String {
+        let result = self
+            .config
+            .as_ref()
+            .map_err(|e| anyhow!("{e}"))
+            .and_then(|config| {
+                let state = Some(state.to_string()).filter(|s| !s.is_empty());
+
+                if !shell::check_state(&self.state, state)? {
+                    return Ok(format!("{}\n", zsh_hook::RELOAD));
+                }
+
+                // Requests are handled one at a time, so the profiles and local config can be
+                // evaluated relative to the current directory.
+                std::env::set_current_dir(dir)?;
+                crate::blat(config, Some(applied))
+            });
+
+        match result {
+            Ok(out) => out,
+            Err(e) => format!("print -ru2 -- {}\n", shell_quote(&format!("Error: {e:#}"))),
+        }
+    }
+
+    fn leader(&self, name: &str, dir: &str) -> String {
+        let result: Result<(Node, &str), String> = match self.config {
+            // The variables depend on the profiles and local config of the directory.
+            Ok(ref config) => std::env::set_current_dir(dir)
+                .map_err(anyhow::Error::from)
+                .and_then(|_| crate::vars_for_cwd(config))
+                .and_then(|vars| Node::root(config, name.to_string(), &vars))
+                .map(|node| (node, self.state.as_str()))
+                .map_err(|e| format!("{e:#}")),
+            Err(ref e) => Err(e.clone()),
+        };
+
+        serde_json::to_string(&result).unwrap_or_default()
+    }
+}
+
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+/// Runs the daemon until it is killed.
+pub fn run(config_file: PathBuf) -> Result<()> {
+    let path = daemon_socket_path();
+    let dir = daemon_socket_dir();
+
+    match std::fs::DirBuilder::new().mode(0o700).create(&dir) {
+        Err(e) if e.kind() != std::io::ErrorKind::AlreadyExists => return Err(e.into()),
+        _ => {}
+    }
+    if !is_private_dir(&dir) {
+        anyhow::bail!("{dir:?} must be a directory which only you can access (mode 0700)");
+    }
+
+    if UnixStream::connect(&path).is_ok() {
+        anyhow::bail!("A blz daemon is already listening on {path:?}");
+    }
+    let _ = std::fs::remove_file(&path);
+
+    let listener = UnixListener::bind(&path)?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+    info!("Listening on {path:?}");
+
+    // The environment of the daemon isn't that of the shells which query it.
+    vars::leave_env_to_shell();
+    let mut daemon = Daemon::new(config_file);
+
+    for stream in listener.incoming() {
+        let result = stream
+            .map_err(anyhow::Error::from)
+            .and_then(|stream| daemon.handle(stream));
+
+        if let Err(e) = result {
+            warn!("Failed to handle a request: {e:?}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Asks the daemon for the tree of combos of a leader key, and the state of the config. Returns
+/// None if the daemon isn't running, so the caller can fall back to parsing the config itself.
+pub fn query_leader(name: &str) -> Option<Result<(Node, String)>> {
+    let path = daemon_socket_path();
+    if !is_trusted_socket(&path) {
+        return None;
+    }
+    let dir = std::env::current_dir().ok()?;
+    let dir = dir.to_str().filter(|dir| !dir.contains(['\t', '\n']))?;
+
+    let mut stream = UnixStream::connect(path).ok()?;
+    writeln!(stream, "leader\t{name}\t{dir}").ok()?;
+
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply).ok()?;
+
+    let result: Result<(Node, String), String> = serde_json::from_str(&reply).ok()?;
+    debug!("Received the tree for leader {name:?} from the daemon");
+
+    // The daemon leaves the environment variables to this process, which has the shell's.
+    Some(
+        result
+            .map(|(mut node, state)| {
+                node.interpolate_env();
+                (node, state)
+            })
+            .map_err(|e| anyhow!(e)),
+    )
+}
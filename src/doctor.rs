@@ -0,0 +1,296 @@
+//! 'blz doctor', which diagnoses the usual reasons for keybinds not working.
+
+use anyhow::{Context, Result};
+use blaze_keys::{
+    CONFIG_DIR, CONFIG_DIR_ARG, CONFIG_DIR_VAR, CONFIG_FILE_NAME, PANIC_FILE_NAME, STATE_DIR,
+    conflicts, daemon_socket_path, is_trusted_socket,
+    resolve::{self, ResolvedKeybind},
+    shell::{self, STATE_VAR, bindkey, nu_hook},
+    yml::GlobalConfig,
+};
+use colored::Colorize;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::configs;
+
+pub(crate) fn ok(message: impl AsRef<str>) {
+    println!("  {} {}", "✓".green(), message.as_ref());
+}
+
+pub(crate) fn warn(message: impl AsRef<str>) {
+    println!("  {} {}", "!".yellow().bold(), message.as_ref());
+}
+
+pub(crate) fn fail(message: impl AsRef<str>) {
+    println!("  {} {}", "✗".red().bold(), message.as_ref());
+}
+
+pub(crate) fn section(title: &str) {
+    println!("\n{}", title.bold());
+}
+
+fn read_file(path: &Path) -> Option<String> {
+    std::fs::read_to_string(path).ok()
+}
+
+fn home() -> PathBuf {
+    shellexpand::tilde("~").to_string().into()
+}
+
+/// The name of the process which ran blz, e.g. the shell.
+fn parent_process() -> Option<String> {
+    let ppid = unsafe { libc::getppid() };
+
+    read_file(Path::new(&format!("/proc/{ppid}/comm"))).map(|comm| comm.trim().to_string())
+}
+
+fn check_shell() {
+    section("Shell");
+
+    match parent_process() {
+        Some(parent) => ok(format!("Invoked from {parent:?}")),
+        None => warn("Couldn't determine the shell which invoked blz"),
+    }
+    if let Ok(login) = std::env::var("SHELL") {
+        ok(format!("Login shell is {login:?}"));
+    }
+    if blaze_keys::is_nushell() {
+        ok("BLZ_SHELL is set, so the nu integration is in use");
+    }
+
+    if unsafe { libc::geteuid() } == 0 {
+        match std::env::var("BLZ_ALLOW_ROOT") {
+            Ok(value) if value != "0" => {
+                warn("Running as root, which is allowed by BLZ_ALLOW_ROOT")
+            }
+            _ => fail("Running as root: blz refuses to run unless 'BLZ_ALLOW_ROOT=1' is exported"),
+        }
+    }
+}
+
+fn check_zsh_hook(state: &str) {
+    section("Zsh hook");
+
+    let zdotdir = std::env::var("ZDOTDIR").map_or_else(|_| home(), PathBuf::from);
+    let zshrc = zdotdir.join(".zshrc");
+
+    match read_file(&zshrc) {
+        Some(rc) if rc.contains("blz --zsh-hook") => ok(format!("Installed in {zshrc:?}")),
+        Some(_) => fail(format!(
+            "Not found in {zshrc:?}: add 'source <(blz --zsh-hook)' to it"
+        )),
+        None => warn(format!("{zshrc:?} doesn't exist")),
+    }
+
+    check_state(state);
+}
+
+fn check_state(state: &str) {
+    match std::env::var(STATE_VAR) {
+        Ok(env) if env == state => ok(format!("{STATE_VAR} matches the config ({state})")),
+        Ok(env) => warn(format!(
+            "{STATE_VAR} is {env:?}, but the config is {state:?}: the hook will reload on the next 'cd' or leader key"
+        )),
+        Err(_) => fail(format!(
+            "{STATE_VAR} isn't set, so the hook hasn't run in this shell: open a new shell"
+        )),
+    }
+}
+
+fn check_nu_hook(state: &str) {
+    let config_home =
+        std::env::var("XDG_CONFIG_HOME").map_or_else(|_| home().join(".config"), PathBuf::from);
+    let config = config_home.join("nushell/config.nu");
+    let source = PathBuf::from(nu_hook::nu_source_location());
+
+    let installed = read_file(&config);
+    if installed.is_none() && !source.exists() && !blaze_keys::is_nushell() {
+        return;
+    }
+
+    section("Nushell hook");
+
+    match installed {
+        Some(config_nu) if config_nu.contains("### blaze-keys: start v1") => {
+            ok(format!("Installed in {config:?}"));
+
+            // The path of the source depends on the config dir, and nu needs it as a literal.
+            let tilde = source
+                .strip_prefix(home())
+                .map(|path| format!("~/{}", path.display()));
+            if !config_nu.contains(&*source.to_string_lossy())
+                && !tilde.is_ok_and(|tilde| config_nu.contains(&tilde))
+            {
+                warn(format!(
+                    "{config:?} doesn't source {source:?}: update the 'source' line of the block"
+                ));
+            }
+        }
+        Some(config_nu) if config_nu.contains("### blaze-keys: start") => fail(format!(
+            "The block in {config:?} is outdated: replace it with the block in the README"
+        )),
+        Some(_) => fail(format!("Not found in {config:?}")),
+        None => warn(format!("{config:?} doesn't exist")),
+    }
+
+    match nu_hook::read_leader_state_from_file() {
+        Some(file_state) if file_state == state => ok(format!("{source:?} matches the config")),
+        Some(_) => warn(format!(
+            "{source:?} is stale: run 'blz porcelain generate-nu-source' and open a new shell"
+        )),
+        None => fail(format!(
+            "{source:?} is missing or outdated: run 'blz porcelain generate-nu-source'"
+        )),
+    }
+
+    if blaze_keys::is_nushell() {
+        check_state(state);
+    }
+}
+
+fn check_configs(config_file: &Path) -> Option<GlobalConfig> {
+    section("Configs");
+
+    let dir_source = if CONFIG_DIR_ARG.get().is_some() {
+        "from --config"
+    } else if std::env::var_os(CONFIG_DIR_VAR).is_some() {
+        "from BLZ_CONFIG_DIR"
+    } else {
+        "from XDG_CONFIG_HOME, or the default"
+    };
+    ok(format!(
+        "Config directory is {:?} ({dir_source})",
+        *CONFIG_DIR
+    ));
+
+    let global = match configs::parse_global_keybinds(config_file) {
+        Some(Ok(global)) => {
+            ok(format!("Global config {config_file:?} is valid"));
+            for source in global.sources.iter().filter(|s| s.path() != config_file) {
+                ok(format!("Merged {:?}", source.path()));
+            }
+            Some(global)
+        }
+        Some(Err(e)) => {
+            fail(format!("{e:#}"));
+            None
+        }
+        None => {
+            warn(format!(
+                "{config_file:?} doesn't exist: run 'blz -g' to create it"
+            ));
+            None
+        }
+    };
+
+    match configs::parse_local_keybinds() {
+        Some(Ok(local)) => {
+            ok(format!("Local config {CONFIG_FILE_NAME:?} applies"));
+            for name in local.inherits.iter().flatten() {
+                ok(format!("Inherits profile {name:?}"));
+            }
+        }
+        Some(Err(e)) => fail(format!("{e:#}")),
+        None => ok("No local config in this directory"),
+    }
+
+    let profiles = resolve::active_profiles(&global);
+    match profiles.is_empty() {
+        true => ok("No profiles apply to this directory"),
+        false => ok(format!("Profiles which apply: {}", profiles.join(", "))),
+    }
+
+    let panic_file = STATE_DIR.join(PANIC_FILE_NAME);
+    if panic_file.exists() {
+        fail(format!(
+            "blz has panicked: see {panic_file:?}, and delete it once it's been dealt with"
+        ));
+    }
+
+    let socket = daemon_socket_path();
+    if socket.exists() && !is_trusted_socket(&socket) {
+        fail(format!(
+            "{socket:?} doesn't belong to you, or its directory isn't private, so it's ignored"
+        ));
+    } else if std::os::unix::net::UnixStream::connect(&socket).is_ok() {
+        ok(format!("The daemon is running at {socket:?}"));
+    }
+
+    global
+}
+
+/// Reads the bindings which zsh has, from a file (or stdin for '-'), or from zsh's defaults.
+/// Returns the listing, and whether it's the user's own bindings.
+pub(crate) fn read_bindkey_listing(file: Option<&str>) -> Result<(String, bool)> {
+    match file {
+        Some("-") => {
+            let mut text = String::new();
+            std::io::stdin().read_to_string(&mut text)?;
+            Ok((text, true))
+        }
+        Some(file) => Ok((
+            std::fs::read_to_string(shellexpand::tilde(file).as_ref())
+                .with_context(|| format!("Failed to read {file:?}"))?,
+            true,
+        )),
+        None => {
+            let output = std::process::Command::new("zsh")
+                .args(["-fc", "bindkey -L"])
+                .output()
+                .context("Failed to run zsh")?;
+            Ok((String::from_utf8_lossy(&output.stdout).into_owned(), false))
+        }
+    }
+}
+
+fn check_conflicts(
+    keybinds: &[ResolvedKeybind],
+    global: &Option<GlobalConfig>,
+    file: Option<&str>,
+) {
+    section("Conflicts with zsh");
+
+    let existing = match read_bindkey_listing(file) {
+        Ok((listing, _)) => bindkey::parse_listing(&listing),
+        Err(e) => {
+            warn(format!("Skipped: {e:#}"));
+            return;
+        }
+    };
+    let found = conflicts::zsh_conflicts(&conflicts::bound_keys(keybinds, global), &existing);
+
+    for conflict in &found {
+        warn(conflict.to_string());
+    }
+    if found.is_empty() {
+        ok("No conflicts");
+    }
+    if file.is_none() {
+        println!(
+            "    {}",
+            "Only zsh's default bindings were checked. To check yours, run: bindkey -L | blz doctor --bindkey -"
+                .dimmed()
+        );
+    }
+}
+
+pub fn run(config_file: &Path, bindkey_file: Option<&str>) -> Result<()> {
+    check_shell();
+
+    let global = check_configs(config_file);
+    let state = shell::config_state(&global);
+
+    if !blaze_keys::is_nushell() {
+        check_zsh_hook(&state);
+    }
+    check_nu_hook(&state);
+
+    let keybinds = crate::resolve_for_cwd(&global).unwrap_or_default();
+    check_conflicts(&keybinds, &global, bindkey_file);
+
+    println!(
+        "\nIf a key doesn't work, run 'blz doctor --keys' to see what your terminal sends for it."
+    );
+    Ok(())
+}
@@ -0,0 +1,686 @@
+//! Edits the text of a YAML config in place, so comments and formatting are preserved.
+//!
+//! The edits only understand the layout which `blz` templates use (block sequences and block
+//! scalars), and every edited config is parsed again before it is returned, so a layout which
+//! isn't understood results in an error rather than a broken config.
+
+use anyhow::{Result, anyhow, bail};
+
+use crate::{
+    keys,
+    nodes::parse_key_sequence,
+    yml::{Combos, GlobalConfig, LocalConfig},
+};
+
+/// Where a keybind is defined.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Scope {
+    Global,
+    Profile(String),
+    /// The local config, which is a separate file.
+    Local,
+}
+
+/// The target of a keybind.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Command(String),
+    Zle(String),
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+fn is_blank_or_comment(line: &str) -> bool {
+    let line = line.trim();
+    line.is_empty() || line.starts_with('#')
+}
+
+/// A YAML scalar, quoted if necessary. Multi-line values are written as JSON strings, which are
+/// also valid YAML, so they fit on one line.
+fn scalar(value: &str) -> String {
+    if value.contains('\n') {
+        return serde_json::to_string(value).unwrap_or_default();
+    }
+    serde_yml::to_string(value)
+        .map(|s| s.trim_end().to_string())
+        .unwrap_or_else(|_| format!("{value:?}"))
+}
+
+fn unquote(value: &str) -> String {
+    serde_yml::from_str::<String>(value).unwrap_or_else(|_| value.to_string())
+}
+
+/// The value of a `key: value` line (which may be the first line of a sequence item), without
+/// any trailing comment.
+fn value_of<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let line = line.trim_start();
+    let line = line.strip_prefix("- ").unwrap_or(line).trim_start();
+    let value = line.strip_prefix(key)?.strip_prefix(':')?.trim();
+
+    Some(match value.starts_with(['"', '\'']) {
+        true => value,
+        false => value.split(" #").next().unwrap_or_default().trim(),
+    })
+}
+
+/// Splits a line into its content and its trailing comment (including the leading whitespace).
+fn split_comment(line: &str) -> (&str, &str) {
+    match line.find(" #") {
+        Some(i) if !line[..i].contains(['"', '\'']) => {
+            let content = line[..i].trim_end();
+            (content, &line[content.len()..])
+        }
+        _ => (line.trim_end(), ""),
+    }
+}
+
+fn is_item(line: &str) -> bool {
+    let line = line.trim_start();
+    line.starts_with("- ") || line == "-"
+}
+
+/// The end (exclusive) of the block which starts after `start`, containing lines which are more
+/// indented than `min_indent` (or items of a sequence at `min_indent`). Trailing blank lines and
+/// comments are excluded.
+fn block_end(lines: &[String], start: usize, min_indent: usize) -> usize {
+    let mut end = start + 1;
+
+    for (i, line) in lines.iter().enumerate().skip(start + 1) {
+        if is_blank_or_comment(line) {
+            continue;
+        }
+        if indent(line) < min_indent || (indent(line) == min_indent && !is_item(line)) {
+            break;
+        }
+        end = i + 1;
+    }
+    end
+}
+
+/// Finds a key at the top level, or directly beneath the key on the `parent` line.
+fn find_child(lines: &[String], parent: Option<usize>, key: &str) -> Option<usize> {
+    let (start, end) = match parent {
+        Some(p) => (p + 1, block_end(lines, p, indent(&lines[p]) + 1)),
+        None => (0, lines.len()),
+    };
+    let child_indent = (start..end)
+        .map(|i| &lines[i])
+        .find(|l| !is_blank_or_comment(l))
+        .map_or(0, |l| indent(l));
+
+    (start..end).find(|&i| {
+        !is_blank_or_comment(&lines[i])
+            && indent(&lines[i]) == child_indent
+            && value_of(&lines[i], key).is_some()
+    })
+}
+
+/// An item of a sequence: its lines, and the indent of its keys.
+#[derive(Debug, Clone, Copy)]
+struct Item {
+    start: usize,
+    end: usize,
+    key_indent: usize,
+}
+
+/// The items of the sequence beneath the key on the given line.
+fn items(lines: &[String], line: usize) -> Vec<Item> {
+    let end = block_end(lines, line, indent(&lines[line]));
+    let starts: Vec<usize> = (line + 1..end)
+        .filter(|&i| is_item(&lines[i]) && !is_blank_or_comment(&lines[i]))
+        .collect();
+    let Some(dash) = starts.first().map(|&i| indent(&lines[i])) else {
+        return vec![];
+    };
+    let starts: Vec<usize> = starts
+        .into_iter()
+        .filter(|&i| indent(&lines[i]) == dash)
+        .collect();
+
+    starts
+        .iter()
+        .enumerate()
+        .map(|(n, &start)| {
+            let next = starts.get(n + 1).copied().unwrap_or(end);
+            let end = (start..next)
+                .rev()
+                .find(|&i| !is_blank_or_comment(&lines[i]))
+                .map_or(start + 1, |i| i + 1);
+            let first = lines[start].trim_start()[1..].trim_start();
+
+            Item {
+                start,
+                end,
+                key_indent: lines[start].len() - first.len(),
+            }
+        })
+        .collect()
+}
+
+/// Finds the line of a key in an item.
+fn find_in_item(lines: &[String], item: Item, key: &str) -> Option<usize> {
+    (item.start..item.end).find(|&i| {
+        (i == item.start || indent(&lines[i]) == item.key_indent)
+            && value_of(&lines[i], key).is_some()
+    })
+}
+
+/// Finds the item of the sequence on `line` where the value of `key` matches.
+fn find_item(
+    lines: &[String],
+    line: usize,
+    key: &str,
+    matches: impl Fn(&str) -> bool,
+) -> Option<Item> {
+    items(lines, line).into_iter().find(|&item| {
+        find_in_item(lines, item, key)
+            .and_then(|i| value_of(&lines[i], key))
+            .is_some_and(|value| matches(&unquote(value)))
+    })
+}
+
+/// Returns the line of the key beneath the parent, adding the key if it's absent.
+fn ensure_child(lines: &mut Vec<String>, parent: Option<usize>, key: &str, indent: usize) -> usize {
+    if let Some(i) = find_child(lines, parent, key) {
+        return i;
+    }
+
+    let at = match parent {
+        Some(p) => block_end(lines, p, self::indent(&lines[p]) + 1),
+        None => lines.len(),
+    };
+    lines.insert(at, format!("{}{key}:", " ".repeat(indent)));
+    at
+}
+
+/// Appends an item to the sequence beneath the key on the given line.
+fn append_item(lines: &mut Vec<String>, line: usize, fields: &[(&str, String)]) {
+    // An empty flow sequence becomes a block sequence.
+    let (content, comment) = split_comment(&lines[line]);
+    if let Some(key) = content.strip_suffix("[]") {
+        lines[line] = format!("{}{comment}", key.trim_end());
+    }
+
+    let existing = items(lines, line);
+    let (dash, at) = match (existing.first(), existing.last()) {
+        (Some(first), Some(last)) => (indent(&lines[first.start]), last.end),
+        _ => (indent(&lines[line]) + 2, line + 1),
+    };
+    let pad = " ".repeat(dash);
+
+    let item = fields.iter().enumerate().map(|(n, (key, value))| match n {
+        0 => format!("{pad}- {key}: {value}"),
+        _ => format!("{pad}  {key}: {value}"),
+    });
+    lines.splice(at..at, item);
+}
+
+/// Removes an item from the sequence beneath the key on the given line.
+fn remove_item(lines: &mut Vec<String>, line: usize, item: Item) {
+    lines.drain(item.start..item.end);
+
+    if items(lines, line).is_empty() {
+        let (content, comment) = split_comment(&lines[line]);
+        lines[line] = format!("{content} []{comment}");
+    }
+}
+
+fn indented(text: &str, indent: usize) -> Vec<String> {
+    text.lines()
+        .map(|l| match l.trim().is_empty() {
+            true => String::new(),
+            false => format!("{}{l}", " ".repeat(indent)),
+        })
+        .collect()
+}
+
+fn join(lines: &[String]) -> String {
+    lines.join("\n") + "\n"
+}
+
+/// Checks that the edited config is still valid on its own. Profiles may extend profiles of other
+/// files, so they're only checked once the config is merged with the files it includes and the
+/// fragments (as 'blz add' does before writing).
+pub fn validate(config: &str) -> Result<GlobalConfig> {
+    let parsed: GlobalConfig = serde_yml::from_str(config)
+        .map_err(|e| anyhow!("The edited config would be invalid: {e}"))?;
+
+    for leader in parsed
+        .global
+        .iter()
+        .flat_map(|g| g.leader_keys.iter().flatten())
+    {
+        leader.combos.flatten().map_err(|e| {
+            anyhow!(
+                "The edited combos of {:?} would be invalid: {e:#}",
+                leader.name()
+            )
+        })?;
+    }
+    Ok(parsed)
+}
+
+/// Checks that the edited local config is still valid.
+pub fn validate_local(config: &str) -> Result<LocalConfig> {
+    serde_yml::from_str(config)
+        .map_err(|e| anyhow!("The edited local config would be invalid: {e}"))
+}
+
+/// Formats a combo in the text form, or returns None if it can't be represented.
+pub fn format_combo(keys: &str, command: &str) -> Option<String> {
+    let keys = keys.replace('=', r"\=");
+
+    let text = if command.contains('\n') {
+        let tag = (0..)
+            .map(|i| format!("END{}", if i == 0 { String::new() } else { i.to_string() }))
+            .find(|tag| !command.lines().any(|l| l.trim() == tag))?;
+        format!("{keys} = <<{tag}\n{command}\n{tag}")
+    } else {
+        format!("{keys} = {command}")
+    };
+
+    // Only use the text if it parses back into the same combo.
+    match Combos::Text(text.clone()).flatten().ok()?.as_slice() {
+        [combo] if combo.command == command && combo.mode.is_none() => Some(text),
+        _ => None,
+    }
+}
+
+/// Finds the leader key with the given name.
+fn find_leader(lines: &[String], leader: &str) -> Option<Item> {
+    let global = find_child(lines, None, "global")?;
+    let leader_keys = find_child(lines, Some(global), "leader_keys")?;
+
+    find_item(lines, leader_keys, "name", |name| name == leader)
+}
+
+/// The form of the combos of a leader key.
+enum CombosForm {
+    /// The line of `combos: |`, and the end of the text.
+    Text(usize, usize),
+    /// The line of `combos:`, which has a list beneath it.
+    List(usize),
+}
+
+fn combos_form(lines: &[String], leader: &str) -> Result<CombosForm> {
+    let item =
+        find_leader(lines, leader).ok_or_else(|| anyhow!("Leader key {leader:?} doesn't exist"))?;
+    let line = find_in_item(lines, item, "combos")
+        .ok_or_else(|| anyhow!("Leader key {leader:?} has no combos"))?;
+    let value = value_of(&lines[line], "combos").unwrap_or_default();
+
+    if value.starts_with('|') {
+        return Ok(CombosForm::Text(
+            line,
+            block_end(lines, line, item.key_indent + 1),
+        ));
+    }
+    if value.is_empty() && !items(lines, line).is_empty() {
+        return Ok(CombosForm::List(line));
+    }
+    bail!(
+        "The combos of leader key {leader:?} can only be edited in the text form (`combos: |`) or the list form"
+    )
+}
+
+/// Inserts lines at the end of the text of the combos.
+fn insert_text(lines: &mut Vec<String>, line: usize, end: usize, text: &str, separate: bool) {
+    let content_indent = lines[line + 1..end]
+        .iter()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| indent(l))
+        .min()
+        .unwrap_or(indent(&lines[line]) + 2);
+
+    let mut new = match separate {
+        true => vec![String::new()],
+        false => vec![],
+    };
+    new.extend(indented(text, content_indent));
+    lines.splice(end..end, new);
+}
+
+/// Appends combos (in the text form) to a leader key. The leader key is created with the given
+/// trigger keys if it doesn't exist.
+pub fn append_combos(
+    config: &str,
+    leader: &str,
+    combos: &str,
+    triggers: Option<(&str, &str)>,
+) -> Result<String> {
+    let mut lines: Vec<String> = config.lines().map(String::from).collect();
+
+    if find_leader(&lines, leader).is_some() {
+        let CombosForm::Text(line, end) = combos_form(&lines, leader)? else {
+            bail!(
+                "Combos can only be added to the text form (`combos: |`), but leader key {leader:?} uses another form"
+            );
+        };
+        insert_text(&mut lines, line, end, combos, true);
+    } else {
+        let (exec_mode, abbr_mode) = triggers.ok_or_else(|| {
+            anyhow!("Leader key {leader:?} doesn't exist, so its trigger keys must be given")
+        })?;
+
+        let global = ensure_child(&mut lines, None, "global", 0);
+        if find_child(&lines, Some(global), "keybinds").is_none() {
+            let at = ensure_child(&mut lines, Some(global), "keybinds", 2);
+            lines[at] += " []";
+        }
+        let leader_keys = ensure_child(&mut lines, Some(global), "leader_keys", 2);
+
+        append_item(
+            &mut lines,
+            leader_keys,
+            &[
+                ("name", scalar(leader)),
+                ("exec_mode", scalar(exec_mode)),
+                ("abbr_mode", scalar(abbr_mode)),
+                ("combos", "|".to_string()),
+            ],
+        );
+
+        let item = find_leader(&lines, leader).expect("the leader key was just added");
+        lines.splice(item.end..item.end, indented(combos, item.key_indent + 2));
+        if item.start > 0 && !lines[item.start - 1].trim().is_empty() {
+            lines.insert(item.start, String::new());
+        }
+    }
+
+    let edited = join(&lines);
+    validate(&edited)?;
+
+    Ok(edited)
+}
+
+/// Adds a combo to a leader key, which must exist.
+pub fn add_combo(config: &str, leader: &str, combo_keys: &str, command: &str) -> Result<String> {
+    let parsed = validate(config)?;
+    let sequence = parse_key_sequence(combo_keys)?;
+
+    let existing = parsed
+        .global
+        .iter()
+        .flat_map(|g| g.leader_keys.iter().flatten())
+        .find(|l| l.name() == leader)
+        .ok_or_else(|| anyhow!("Leader key {leader:?} doesn't exist"))?
+        .combos
+        .flatten()?;
+
+    if let Some(combo) = existing
+        .iter()
+        .find(|c| parse_key_sequence(&c.keys).ok().as_ref() == Some(&sequence))
+    {
+        bail!(
+            "Leader key {leader:?} already has a combo for {combo_keys:?} ({:?})",
+            combo.command
+        );
+    }
+
+    let mut lines: Vec<String> = config.lines().map(String::from).collect();
+
+    match combos_form(&lines, leader)? {
+        CombosForm::Text(line, end) => {
+            let text = format_combo(combo_keys, command)
+                .ok_or_else(|| anyhow!("Can't write {combo_keys:?} as a leader combo"))?;
+            insert_text(&mut lines, line, end, &text, false);
+        }
+        CombosForm::List(line) => append_item(
+            &mut lines,
+            line,
+            &[("keys", scalar(combo_keys)), ("command", scalar(command))],
+        ),
+    }
+
+    let edited = join(&lines);
+    validate(&edited)?;
+
+    Ok(edited)
+}
+
+/// Removes a combo from a leader key.
+pub fn remove_combo(config: &str, leader: &str, combo_keys: &str) -> Result<String> {
+    let sequence = parse_key_sequence(combo_keys)?;
+    let is_match = |keys: &str| parse_key_sequence(keys).ok().as_ref() == Some(&sequence);
+
+    let mut lines: Vec<String> = config.lines().map(String::from).collect();
+
+    match combos_form(&lines, leader)? {
+        CombosForm::Text(line, end) => {
+            let mut i = line + 1;
+            let found = loop {
+                if i >= end {
+                    break None;
+                }
+                let Ok(Some((keys, command))) = keys::parse_combo(&lines[i]) else {
+                    i += 1;
+                    continue;
+                };
+                let last = match keys::heredoc_tag(&command) {
+                    Some(tag) => (i + 1..end).find(|&j| lines[j].trim() == tag).unwrap_or(i),
+                    None => i,
+                };
+                if is_match(&keys) {
+                    break Some((i, last));
+                }
+                i = last + 1;
+            };
+
+            let (first, last) = found
+                .ok_or_else(|| anyhow!("Leader key {leader:?} has no combo {combo_keys:?}"))?;
+            lines.drain(first..=last);
+        }
+        CombosForm::List(line) => {
+            let item = find_item(&lines, line, "keys", is_match)
+                .ok_or_else(|| anyhow!("Leader key {leader:?} has no combo {combo_keys:?}"))?;
+            remove_item(&mut lines, line, item);
+        }
+    }
+
+    let edited = join(&lines);
+    validate(&edited)?;
+
+    Ok(edited)
+}
+
+/// Finds the line of the keybinds of the scope, adding it if `create` is set.
+fn find_keybinds(lines: &mut Vec<String>, scope: &Scope, create: bool) -> Result<Option<usize>> {
+    let line = match scope {
+        Scope::Local if create => Some(ensure_child(lines, None, "keybinds", 0)),
+        Scope::Local => find_child(lines, None, "keybinds"),
+        Scope::Global if create => {
+            let global = ensure_child(lines, None, "global", 0);
+            Some(ensure_child(lines, Some(global), "keybinds", 2))
+        }
+        Scope::Global => {
+            find_child(lines, None, "global").and_then(|g| find_child(lines, Some(g), "keybinds"))
+        }
+        Scope::Profile(name) => {
+            let item = find_child(lines, None, "profiles")
+                .and_then(|p| find_item(lines, p, "name", |n| n == name))
+                .ok_or_else(|| anyhow!("Profile {name:?} doesn't exist"))?;
+
+            match find_in_item(lines, item, "keybinds") {
+                Some(line) => Some(line),
+                None if create => {
+                    lines.insert(
+                        item.end,
+                        format!("{}keybinds:", " ".repeat(item.key_indent)),
+                    );
+                    Some(item.end)
+                }
+                None => None,
+            }
+        }
+    };
+    Ok(line)
+}
+
+fn validate_scope(config: &str, scope: &Scope) -> Result<()> {
+    match scope {
+        Scope::Local => validate_local(config).map(|_| ()),
+        _ => validate(config).map(|_| ()),
+    }
+}
+
+/// Adds a keybind to the global config, a profile or the local config. A raw key is an escape
+/// sequence which is given to `bindkey` as it is.
+pub fn add_bind(
+    config: &str,
+    scope: &Scope,
+    key: &str,
+    action: &Action,
+    raw: bool,
+) -> Result<String> {
+    let mut lines: Vec<String> = config.lines().map(String::from).collect();
+    let line = find_keybinds(&mut lines, scope, true)?.expect("the keybinds were just added");
+
+    if find_item(&lines, line, "key", |k| k == key).is_some() {
+        bail!("{key:?} is already bound; remove it first");
+    }
+
+    let action = match action {
+        Action::Command(command) => ("command", scalar(command)),
+        Action::Zle(widget) => ("zle", scalar(widget)),
+    };
+    let mut fields = vec![("key", scalar(key)), action];
+    if raw {
+        fields.push(("raw", "true".to_string()));
+    }
+    append_item(&mut lines, line, &fields);
+
+    let edited = join(&lines);
+    validate_scope(&edited, scope)?;
+
+    Ok(edited)
+}
+
+/// Removes a keybind from the global config, a profile or the local config.
+pub fn remove_bind(config: &str, scope: &Scope, key: &str) -> Result<String> {
+    let mut lines: Vec<String> = config.lines().map(String::from).collect();
+
+    let item = find_keybinds(&mut lines, scope, false)?
+        .and_then(|line| Some((line, find_item(&lines, line, "key", |k| k == key)?)));
+    let Some((line, item)) = item else {
+        bail!("{key:?} is not bound");
+    };
+    remove_item(&mut lines, line, item);
+
+    let edited = join(&lines);
+    validate_scope(&edited, scope)?;
+
+    Ok(edited)
+}
+
+#[cfg(test)]
+const TEST_CONFIG: &str = "global:
+  keybinds: []  # No keybinds yet.
+  leader_keys:
+    - name: Leader1 # The main one.
+      exec_mode: \"Ctrl-s\"
+      abbr_mode: \"Alt-s\"
+      combos: |
+        -- git
+        gs = git status
+        dp = <<END
+        docker compose up
+        END
+
+    - name: Leader2
+      exec_mode: \"Ctrl-o\"
+      abbr_mode: \"Alt-o\"
+      combos:
+        - keys: ib
+          command: localhost:5000/backend
+
+# A comment about the profiles.
+profiles:
+  - name: Rust
+    conditions:
+      - glob: Cargo.toml
+";
+
+#[test]
+fn test_append_combos() {
+    let config = TEST_CONFIG;
+
+    let edited = append_combos(config, "Leader1", "gl = git log", None).unwrap();
+    assert_eq!(
+        edited,
+        config.replace("        END\n", "        END\n\n        gl = git log\n")
+    );
+
+    let edited = append_combos(config, "Other", "x = exit", Some(("Ctrl-n", "Alt-n"))).unwrap();
+    assert!(edited.contains(
+        "          command: localhost:5000/backend\n\n    - name: Other\n      exec_mode: Ctrl-n\n      abbr_mode: Alt-n\n      combos: |\n        x = exit\n\n# A comment"
+    ));
+
+    assert!(append_combos(config, "Other", "x = exit", None).is_err());
+    assert!(append_combos(config, "Leader1", "<bad> = exit", None).is_err());
+    assert!(append_combos(config, "Leader2", "x = exit", None).is_err());
+
+    let edited = append_combos("# Empty.\n", "L", "x = exit", Some(("Ctrl-o", "Alt-o"))).unwrap();
+    validate(&edited).unwrap();
+}
+
+#[test]
+fn test_edit_combos() {
+    let edited = add_combo(TEST_CONFIG, "Leader1", "gl", "git log").unwrap();
+    assert!(edited.contains("        END\n        gl = git log\n\n    - name: Leader2"));
+    assert!(add_combo(TEST_CONFIG, "Leader1", "gs", "git status -s").is_err());
+
+    let edited = remove_combo(&edited, "Leader1", "dp").unwrap();
+    assert!(edited.contains("        gs = git status\n        gl = git log\n"));
+    assert!(remove_combo(&edited, "Leader1", "dp").is_err());
+
+    let edited = add_combo(TEST_CONFIG, "Leader2", "if", "localhost:5000/frontend").unwrap();
+    assert!(edited.contains(
+        "          command: localhost:5000/backend\n        - keys: if\n          command: localhost:5000/frontend\n"
+    ));
+    let edited = remove_combo(&edited, "Leader2", "ib").unwrap();
+    assert!(!edited.contains("backend"));
+    let edited = remove_combo(&edited, "Leader2", "if").unwrap();
+    assert!(edited.contains("      combos: []\n"));
+}
+
+#[test]
+fn test_edit_binds() {
+    let action = Action::Command("cargo build".into());
+
+    let edited = add_bind(TEST_CONFIG, &Scope::Global, "Alt-b", &action, false).unwrap();
+    assert!(edited.contains(
+        "  keybinds:  # No keybinds yet.\n    - key: Alt-b\n      command: cargo build\n"
+    ));
+    assert!(add_bind(&edited, &Scope::Global, "Alt-b", &action, false).is_err());
+    let removed = remove_bind(&edited, &Scope::Global, "Alt-b").unwrap();
+    assert!(removed.contains("  keybinds: []  # No keybinds yet.\n"));
+
+    let profile = Scope::Profile("Rust".into());
+    let edited = add_bind(TEST_CONFIG, &profile, "F9", &action, false).unwrap();
+    assert!(edited.ends_with("    keybinds:\n      - key: F9\n        command: cargo build\n"));
+    assert!(
+        add_bind(
+            TEST_CONFIG,
+            &Scope::Profile("C".into()),
+            "F9",
+            &action,
+            false
+        )
+        .is_err()
+    );
+
+    let edited = add_bind(
+        "",
+        &Scope::Local,
+        "F10",
+        &Action::Zle("push-line".into()),
+        false,
+    )
+    .unwrap();
+    assert_eq!(edited, "keybinds:\n  - key: F10\n    zle: push-line\n");
+    let edited = add_bind(&edited, &Scope::Local, "^[[1;5A", &action, true).unwrap();
+    assert!(edited.ends_with("  - key: ^[[1;5A\n    command: cargo build\n    raw: true\n"));
+    assert!(remove_bind(&edited, &Scope::Local, "F9").is_err());
+}
@@ -0,0 +1,121 @@
+//! 'blz import', which proposes leader combos for the aliases and abbreviations of other tools.
+
+use anyhow::{Context, Result, anyhow};
+use blaze_keys::{CONFIG_DIR, aliases, edit, yml::GlobalConfig};
+use colored::Colorize;
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::cli::ImportSource;
+use crate::{config_edit, configs};
+
+fn run_tool(program: &str, args: &[&str]) -> Result<String> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .with_context(|| format!("Failed to run {program:?}"))?;
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn zsh_abbr_file() -> PathBuf {
+    if let Ok(file) = std::env::var("ABBR_USER_ABBREVIATIONS_FILE") {
+        return file.into();
+    }
+
+    let config_home = std::env::var("XDG_CONFIG_HOME")
+        .unwrap_or_else(|_| shellexpand::tilde("~/.config").to_string());
+    PathBuf::from(config_home).join("zsh-abbr/user-abbreviations")
+}
+
+/// Reads the definitions from the file, or from the tool itself if no file is given.
+fn read_definitions(source: ImportSource, file: Option<&str>) -> Result<String> {
+    match file {
+        Some("-") => {
+            let mut text = String::new();
+            std::io::stdin().read_to_string(&mut text)?;
+            Ok(text)
+        }
+        Some(file) => std::fs::read_to_string(shellexpand::tilde(file).as_ref())
+            .with_context(|| format!("Failed to read {file:?}")),
+        None => match source {
+            ImportSource::Zsh => run_tool("zsh", &["-ic", "alias"]),
+            ImportSource::Git => run_tool("git", &["config", "--get-regexp", r"^alias\."]),
+            ImportSource::ZshAbbr => {
+                let file = zsh_abbr_file();
+                std::fs::read_to_string(&file).with_context(|| format!("Failed to read {file:?}"))
+            }
+            ImportSource::Fish => run_tool("fish", &["-c", "abbr --show"]),
+        },
+    }
+}
+
+/// The combos which the leader key already has.
+fn existing_combos(global: &Option<GlobalConfig>, leader: &str) -> Result<Vec<(String, String)>> {
+    let leaders = global
+        .as_ref()
+        .and_then(|g| g.global.as_ref())
+        .and_then(|g| g.leader_keys.as_ref());
+
+    match leaders.into_iter().flatten().find(|l| l.name() == leader) {
+        Some(leader) => Ok(leader
+            .combos
+            .flatten()?
+            .into_iter()
+            .map(|c| (c.keys, c.command))
+            .collect()),
+        None => Ok(vec![]),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn run(
+    config_file: &PathBuf,
+    global: &Option<GlobalConfig>,
+    source: ImportSource,
+    file: Option<&str>,
+    leader: &str,
+    write: bool,
+    exec_mode: Option<&str>,
+    abbr_mode: Option<&str>,
+) -> Result<()> {
+    let text = read_definitions(source, file)?;
+
+    let aliases = match source {
+        ImportSource::Zsh => aliases::parse_zsh_aliases(&text),
+        ImportSource::Git => aliases::parse_git_aliases(&text),
+        ImportSource::ZshAbbr => aliases::parse_zsh_abbr(&text),
+        ImportSource::Fish => aliases::parse_fish_abbr(&text),
+    };
+
+    let proposal = aliases::propose(&aliases, &existing_combos(global, leader)?);
+
+    for warning in &proposal.warnings {
+        eprintln!("{}: {warning}", "WARNING".yellow());
+    }
+    if proposal.count == 0 {
+        return Err(anyhow!("Found nothing to import"));
+    }
+
+    if !write {
+        print!("{}", proposal.text);
+        eprintln!(
+            "Proposed {} combos. Use '--write' to add them to the leader key {leader:?} in {config_file:?}.",
+            proposal.count
+        );
+        return Ok(());
+    }
+
+    // The edited config is validated before it's written, and the original is backed up.
+    let triggers = exec_mode.zip(abbr_mode);
+    config_edit::edit_global_file(config_file, |config| {
+        edit::append_combos(config, leader, &proposal.text, triggers)
+    })?;
+
+    println!(
+        "Added {} combos to the leader key {leader:?}. The config will now be opened for review.",
+        proposal.count
+    );
+    configs::edit_config_file(&CONFIG_DIR, config_file)
+}
@@ -1,11 +1,16 @@
 #![allow(dead_code)]
 
-use crate::nodes::CharWithModifiers;
-use crate::yml::Keybind;
+use crate::nodes::{CharWithModifiers, Modifier, NamedKey, parse_key_sequence};
+use crate::shell::bindkey;
+use crate::yml::{Action, Keybind, Step, Widget};
 use log::info;
 use once_cell::sync::Lazy;
 use phf::phf_map;
 use regex::Regex;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::hash::Hasher;
 
 static FUNCTION_KEYS: phf::Map<&str, &str> = phf_map! {
     "F1" => "^[OP",
@@ -25,10 +30,9 @@ static FUNCTION_KEYS: phf::Map<&str, &str> = phf_map! {
 static REGEX_ALT: Lazy<Regex> = Lazy::new(|| Regex::new("^(Alt|A|alt)-").unwrap());
 static REGEX_CTRL: Lazy<Regex> = Lazy::new(|| Regex::new("^(Ctrl|C|ctrl)-").unwrap());
 static REGEX_LEADER_COMMENT: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\s*--").unwrap());
-static REGEX_LEADER_COMBO: Lazy<Regex> =
-    Lazy::new(|| Regex::new(r"^\s*([^=]*?)\s*=\s*([^=]*)$").unwrap());
-
-static REGEX_COMBO_VALIDATE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(<.*?>)(?:|[^>^<])").unwrap());
+static WIDGET_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[A-Za-z0-9_.-]+$").unwrap());
+static REGEX_LEADER_HEREDOC: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(@[a-z]+\s+)?<<\s*([A-Za-z_][A-Za-z0-9_]*)$").unwrap());
 
 pub fn get_key_name(key: &str) -> Option<&str> {
     FUNCTION_KEYS.get(key).map(|s| s.as_ref())
@@ -64,6 +68,18 @@ impl From<CharWithModifiers> for NuKey {
                 modifier: None,
                 char: format!("char_{char}"),
             },
+            CharWithModifiers::Named(key, modifier) => NuKey {
+                modifier: match modifier {
+                    Modifier::None => None,
+                    Modifier::Shift => Some("shift"),
+                    Modifier::Ctrl => Some("control"),
+                    Modifier::Alt => Some("alt"),
+                },
+                char: match key {
+                    NamedKey::Tab if modifier == Modifier::Shift => "backtab".into(),
+                    key => key.name().to_lowercase(),
+                },
+            },
         }
     }
 }
@@ -111,20 +127,33 @@ pub fn print_human_keys(
     command: &Option<String>,
     zle: &Option<String>,
 ) -> anyhow::Result<()> {
+    println!("{}", format_human_keys(keybind, key_raw, command, zle)?);
+
+    Ok(())
+}
+
+pub fn format_human_keys(
+    keybind: &Keybind,
+    key_raw: &str,
+    command: &Option<String>,
+    zle: &Option<String>,
+) -> anyhow::Result<String> {
     let out;
 
-    if let Some(command) = command {
+    if keybind.widget()?.is_some() {
+        out = format!("'{}' (widget)", keybind.target());
+    } else if let Some(command) = command {
         out = format!("'{command}'");
     } else if let Some(zle) = zle {
         out = format!("'{}' (zle builtin)", zle);
     } else {
-        anyhow::bail!("The config item for '{key_raw}' must set either 'command' or 'zle'.")
+        anyhow::bail!(
+            "The config item for '{key_raw}' must set either 'command', 'zle' or 'steps'."
+        )
     }
 
     let width = 7;
-    println!("{:<width$} ----->  {out}", keybind.key);
-
-    Ok(())
+    Ok(format!("{:<width$} ----->  {out}", keybind.key))
 }
 
 pub fn print_bindkey_zsh(
@@ -133,53 +162,369 @@ pub fn print_bindkey_zsh(
     command: &Option<String>,
     zle: &Option<String>,
 ) -> anyhow::Result<()> {
-    let command_out;
+    let command_out = format_bindkey_zsh(keybind, key_raw, command, zle, false)?;
+
+    println!("{command_out}");
+    info!("{command_out}");
+
+    Ok(())
+}
+
+/// The name of the zle widget which is generated for a keybind with steps.
+pub fn widget_name(key_raw: &str) -> String {
+    format!("_blz_bind_{:x}", hash(&bindkey::normalize(key_raw)))
+}
 
-    if let Some(command) = command {
+/// The name of the shell function which the hooks generate for the script of a combo.
+pub fn script_name(script: &str) -> String {
+    format!("_blz_script_{:x}", hash(script))
+}
+
+fn quote_zsh(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+/// Defines a zle widget which runs the steps or script, and binds the key to it. If `record` is
+/// set, the widget first records the use of the key (see [`crate::stats`]).
+fn format_widget_zsh(
+    key_raw: &str,
+    widget: &Widget,
+    record: Option<&str>,
+) -> anyhow::Result<String> {
+    let name = widget_name(key_raw);
+    let record = record.map(|key| format!("_blz_record {}", quote_zsh(key)));
+
+    let steps = match widget {
+        Widget::Steps(steps) => steps,
+        // The script may span lines, or end with a comment.
+        Widget::Script(script) => {
+            let record = record.map(|r| r + "\n").unwrap_or_default();
+            return Ok(format!(
+                "function {name} {{\n{record}{}\n}}; zle -N {name}; bindkey '{key_raw}' {name}",
+                script.trim_end()
+            ));
+        }
+    };
+
+    let body: Vec<String> = record
+        .into_iter()
+        .map(Ok)
+        .chain(steps.iter().map(|step| match step {
+            Step::Zle { zle } if WIDGET_REGEX.is_match(zle) => Ok(format!("zle {zle}")),
+            Step::Zle { zle } => Err(anyhow::anyhow!("Invalid zle widget name {zle:?}")),
+            Step::Insert { insert } => Ok(format!("LBUFFER+={}", quote_zsh(insert))),
+            Step::Action(Action::Accept) => Ok("zle accept-line".to_string()),
+        }))
+        .collect::<anyhow::Result<_>>()?;
+
+    Ok(format!(
+        "function {name} {{ {}; }}; zle -N {name}; bindkey '{key_raw}' {name}",
+        body.join("; ")
+    ))
+}
+
+/// The zsh code which binds the key. If `record` is set, every keybind is bound to a widget which
+/// records its use, and then types the command or runs the zle widget.
+pub fn format_bindkey_zsh(
+    keybind: &Keybind,
+    key_raw: &str,
+    command: &Option<String>,
+    zle: &Option<String>,
+    record: bool,
+) -> anyhow::Result<String> {
+    let command_out;
+    let record_key = record.then_some(keybind.key.as_str());
+
+    if let Some(widget) = keybind.widget()? {
+        command_out = format_widget_zsh(key_raw, &widget, record_key)?;
+    } else if record && let Some(command) = command {
+        // The keys are typed as with 'bindkey -s', so '^M' etc. still work.
+        let typed = quote_zsh(&format!("{command}^M"));
+        let script = format!("zle -U ${{(g:ce:)${{:-{typed}}}}}");
+        command_out = format_widget_zsh(key_raw, &Widget::Script(script), record_key)?;
+    } else if record && let Some(zle) = zle {
+        let steps = vec![Step::Zle { zle: zle.clone() }];
+        command_out = format_widget_zsh(key_raw, &Widget::Steps(steps), record_key)?;
+    } else if let Some(command) = command {
         command_out = format!("bindkey -s '{}' \"{}^M\"", key_raw, command);
     } else if let Some(zle) = zle {
         command_out = format!("bindkey '{}' \"{}\"", key_raw, zle);
     } else {
-        anyhow::bail!("The config item for '{key_raw}' must set either 'command' or 'zle'.");
+        anyhow::bail!(
+            "The config item for '{key_raw}' must set either 'command', 'zle' or 'steps'."
+        );
     }
 
     let width = 50;
-    let command_out = format!("{:<width$}  # <--- {}", command_out, keybind.key);
+    Ok(format!("{:<width$}  # <--- {}", command_out, keybind.key))
+}
 
-    println!("{command_out}");
-    info!("{command_out}");
+/// The bindings which have been applied in the shell, so that only the bindings which differ need
+/// to be emitted when the directory changes, and the keys which are no longer bound can be
+/// unbound.
+///
+/// The state is stored in the shell as pairs of the key (as hex, prefixed with `k`) and the hash of
+/// its binding.
+#[derive(Debug, Default)]
+pub struct AppliedBinds {
+    binds: BTreeMap<String, u64>,
+    /// The keys which have been bound since the state was parsed.
+    updated: BTreeSet<String>,
+}
 
-    Ok(())
+pub const APPLIED_BINDS_VAR: &str = "BLZ_APPLIED_BINDS";
+
+impl AppliedBinds {
+    pub fn parse(state: &str) -> AppliedBinds {
+        // Pairs from older versions, which only stored the hash of the key, are skipped.
+        let binds = state
+            .split_whitespace()
+            .filter_map(|pair| {
+                let (key, bind) = pair.split_once(':')?;
+                Some((
+                    decode_hex(key.strip_prefix('k')?)?,
+                    u64::from_str_radix(bind, 16).ok()?,
+                ))
+            })
+            .collect();
+
+        AppliedBinds {
+            binds,
+            updated: BTreeSet::new(),
+        }
+    }
+
+    /// Records the binding for the key, returning whether it differs from the applied binding.
+    pub fn update(&mut self, key_raw: &str, binding: &str) -> bool {
+        let bind = hash(binding);
+        self.updated.insert(key_raw.to_string());
+
+        self.binds.insert(key_raw.to_string(), bind) != Some(bind)
+    }
+
+    /// Forgets the keys which were applied before, but haven't been bound since, e.g. the global
+    /// keybinds in a directory whose profile overrides them. Returns the shell code which restores
+    /// zsh's own binding for each of them (see `_blz_unbind` in [`crate::shell::zsh_hook`]).
+    pub fn unbind_stale(&mut self) -> Vec<String> {
+        let stale: Vec<String> = self
+            .binds
+            .keys()
+            .filter(|key| !self.updated.contains(*key))
+            .cloned()
+            .collect();
+
+        stale
+            .into_iter()
+            .map(|key| {
+                self.binds.remove(&key);
+                format!("_blz_unbind {}", quote_zsh(&key))
+            })
+            .collect()
+    }
+
+    /// The shell code which stores the state for the next invocation.
+    pub fn export(&self) -> String {
+        let pairs: Vec<String> = self
+            .binds
+            .iter()
+            .map(|(key, bind)| format!("k{}:{bind:x}", encode_hex(key)))
+            .collect();
+
+        format!("typeset -g {APPLIED_BINDS_VAR}='{}'", pairs.join(" "))
+    }
+}
+
+fn encode_hex(text: &str) -> String {
+    text.bytes().map(|b| format!("{b:02x}")).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<String> {
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    String::from_utf8(bytes).ok()
+}
+
+/// The zsh code which applies the keybinds, as printed by 'blz porcelain blat'. If `record` is
+/// set, the uses of the keybinds are recorded (see [`crate::stats`]).
+///
+/// If the state of the previously applied keybinds is given, only the keybinds which differ are
+/// included, along with the code which restores the keys which are no longer bound, followed by
+/// the new state.
+pub fn blat_zsh(
+    keybinds: &[Keybind],
+    applied: Option<&str>,
+    record: bool,
+) -> anyhow::Result<String> {
+    let out = RefCell::new(String::new());
+    let applied = applied.map(|state| RefCell::new(AppliedBinds::parse(state)));
+
+    emit_keybinds(keybinds, &|keybind, key_raw, command, zle| {
+        let binding = format_bindkey_zsh(keybind, key_raw, command, zle, record)?;
+
+        if applied
+            .as_ref()
+            .is_none_or(|applied| applied.borrow_mut().update(key_raw, &binding))
+        {
+            writeln!(out.borrow_mut(), "{binding}")?;
+            info!("{binding}");
+        }
+        Ok(())
+    })?;
+
+    if let Some(applied) = applied {
+        let mut applied = applied.into_inner();
+        for unbind in applied.unbind_stale() {
+            writeln!(out.borrow_mut(), "{unbind}")?;
+            info!("{unbind}");
+        }
+        writeln!(out.borrow_mut(), "{}", applied.export())?;
+    }
+
+    Ok(out.into_inner())
+}
+
+fn hash(value: &str) -> u64 {
+    let mut hasher = fnv::FnvHasher::default();
+    hasher.write(value.as_bytes());
+    hasher.finish()
+}
+
+#[test]
+fn test_applied_binds() {
+    let mut applied = AppliedBinds::default();
+    assert!(applied.update("^g", "bindkey -s '^g' \"git status^M\""));
+    assert!(!applied.update("^g", "bindkey -s '^g' \"git status^M\""));
+
+    let export = applied.export();
+    let state = export.split_once('=').unwrap().1.trim_matches('\'');
+    let mut applied = AppliedBinds::parse(state);
+
+    assert!(!applied.update("^g", "bindkey -s '^g' \"git status^M\""));
+    assert!(applied.update("^g", "bindkey -s '^g' \"git log^M\""));
+    assert!(applied.update("^h", "bindkey -s '^h' \"git log^M\""));
+
+    // The keys are quoted for the shell.
+    let mut applied = AppliedBinds::default();
+    applied.update("^['", "bindkey -s \"^['\" \"git log^M\"");
+    let export = applied.export();
+    let state = export.split_once('=').unwrap().1.trim_matches('\'');
+    let mut applied = AppliedBinds::parse(state);
+    assert_eq!(applied.unbind_stale(), [r"_blz_unbind '^['\'''"]);
+}
+
+#[test]
+fn test_blat_unbinds_stale_keys() {
+    let keybinds: Vec<Keybind> =
+        serde_yml::from_str("[{key: Alt-g, command: git status}, {key: Alt-h, command: git log}]")
+            .unwrap();
+    let out = blat_zsh(&keybinds, Some(""), false).unwrap();
+    let state = out.lines().last().unwrap().split_once('=').unwrap().1;
+    let state = state.trim_matches('\'');
+
+    // E.g. a profile with 'mode: override' replaces both keybinds.
+    let keybinds: Vec<Keybind> =
+        serde_yml::from_str("[{key: Alt-h, command: cargo build}]").unwrap();
+    let out = blat_zsh(&keybinds, Some(state), false).unwrap();
+    let lines: Vec<&str> = out.lines().collect();
+
+    assert!(lines[0].starts_with("bindkey -s '\\eh' \"cargo build^M\""));
+    assert_eq!(lines[1], "_blz_unbind '\\eg'");
+    assert_eq!(lines.len(), 3);
+
+    // The key which was unbound is forgotten, so it's only unbound once.
+    let state = lines[2].split_once('=').unwrap().1.trim_matches('\'');
+    let out = blat_zsh(&keybinds, Some(state), false).unwrap();
+    assert_eq!(out.lines().count(), 1);
+}
+
+#[test]
+fn test_format_widget() {
+    let keybind: Keybind = serde_yml::from_str(
+        r#"
+key: "Alt-g"
+steps:
+  - zle: push-line
+  - insert: "git commit -m 'wip'"
+  - accept
+"#,
+    )
+    .unwrap();
+    let line = format_bindkey_zsh(&keybind, "^[g", &None, &None, false).unwrap();
+    let name = widget_name("^[g");
+    assert!(line.starts_with(&format!(
+        r#"function {name} {{ zle push-line; LBUFFER+='git commit -m '\''wip'\'''; zle accept-line; }}; zle -N {name}; bindkey '^[g' {name}"#
+    )));
+
+    let keybind: Keybind =
+        serde_yml::from_str("{key: Alt-l, command: 'ls -lah', insert_only: true}").unwrap();
+    let line = format_bindkey_zsh(&keybind, "^[l", &keybind.command, &None, false).unwrap();
+    assert!(line.contains("{ LBUFFER+='ls -lah'; }"));
+
+    let keybind: Keybind =
+        serde_yml::from_str("{key: Alt-t, script: 'BUFFER=\"time $BUFFER\"'}").unwrap();
+    let line = format_bindkey_zsh(&keybind, "^[t", &None, &None, false).unwrap();
+    let name = widget_name("^[t");
+    assert!(line.starts_with(&format!(
+        "function {name} {{\nBUFFER=\"time $BUFFER\"\n}}; zle -N {name}; bindkey '^[t' {name}"
+    )));
+
+    // Recording the use of a key binds it to a widget.
+    let keybind: Keybind = serde_yml::from_str("{key: Alt-l, command: 'ls -lah'}").unwrap();
+    let line = format_bindkey_zsh(&keybind, "^[l", &keybind.command, &None, true).unwrap();
+    assert!(line.contains("{\n_blz_record 'Alt-l'\nzle -U ${(g:ce:)${:-'ls -lah^M'}}\n}"));
+
+    for yml in [
+        "{key: Alt-l, script: 'true', zle: push-line}",
+        "{key: Alt-l, command: ls, steps: [accept]}",
+        "{key: Alt-l, zle: push-line, insert_only: true}",
+        "{key: Alt-l, steps: [{zle: 'push-line; rm'}]}",
+    ] {
+        let keybind: Keybind = serde_yml::from_str(yml).unwrap();
+        assert!(
+            format_bindkey_zsh(&keybind, "^[l", &keybind.command, &keybind.zle, false).is_err()
+        );
+    }
 }
 
 pub fn emit_keybinds<T>(keybinds: &[Keybind], print_bindkey_fn: &T) -> anyhow::Result<()>
 where
-    T: Fn(&Keybind, &str, &Option<String>, &Option<String>) -> anyhow::Result<()>,
+    T: Fn(&Keybind, &str, &Option<String>, &Option<String>) -> anyhow::Result<()> + ?Sized,
 {
     for k in keybinds {
-        let Keybind {
-            key,
-            command,
-            zle,
-            raw,
-        } = k;
-
-        let repr = get_key_zsh_representation(&key);
-
-        if raw.unwrap_or(false) {
-            print_bindkey_fn(k, &key, command, zle)?;
-        } else if let Some(repr) = repr {
-            match repr {
-                KeyOrLeader::Key(repr) => {
-                    print_bindkey_fn(k, &repr, command, zle)?;
-                }
-                KeyOrLeader::LeaderCombo(_) => (), // We don't need to handle this here.
+        emit_keybind(k, print_bindkey_fn)?;
+    }
+    Ok(())
+}
+
+pub fn emit_keybind<T>(k: &Keybind, print_bindkey_fn: &T) -> anyhow::Result<()>
+where
+    T: Fn(&Keybind, &str, &Option<String>, &Option<String>) -> anyhow::Result<()> + ?Sized,
+{
+    let Keybind {
+        key,
+        command,
+        zle,
+        raw,
+        ..
+    } = k;
+
+    let repr = get_key_zsh_representation(key);
+
+    if raw.unwrap_or(false) {
+        print_bindkey_fn(k, key, command, zle)?;
+    } else if let Some(repr) = repr {
+        match repr {
+            KeyOrLeader::Key(repr) => {
+                print_bindkey_fn(k, &repr, command, zle)?;
             }
-        } else {
-            anyhow::bail!(
-                "Unable to generate keybind '{key}', key is not supported - if this is not a typo, please use 'raw: true' in the .yml file for this key"
-            );
+            KeyOrLeader::LeaderCombo(_) => (), // We don't need to handle this here.
         }
+    } else {
+        anyhow::bail!(
+            "Unable to generate keybind '{key}', key is not supported - if this is not a typo, please use 'raw: true' in the .yml file for this key"
+        );
     }
     Ok(())
 }
@@ -192,40 +537,173 @@ fn test_parse_combo() {
 
     parse_combo("<C-s>glo = git log --oneline").unwrap();
     parse_combo("<C-s>g<A-g>lo = git log --oneline").unwrap();
+
+    assert_eq!(
+        parse_combo("ev = env FOO=bar make").unwrap(),
+        Some(("ev".into(), "env FOO=bar make".into()))
+    );
+    assert_eq!(
+        parse_combo("gl = git log --format=%h").unwrap(),
+        Some(("gl".into(), "git log --format=%h".into()))
+    );
+    assert_eq!(
+        parse_combo(r"a\=b = echo").unwrap(),
+        Some(("a=b".into(), "echo".into()))
+    );
+    // A '<' which doesn't start a key in angle brackets is the '<' key.
+    assert_eq!(
+        parse_combo("< = popd").unwrap(),
+        Some(("<".into(), "popd".into()))
+    );
+    assert_eq!(
+        parse_combo("<C-s>< = echo a > b").unwrap(),
+        Some(("<C-s><".into(), "echo a > b".into()))
+    );
 }
 
+#[test]
+fn test_parse_combos_heredoc() {
+    let combos = parse_combos(
+        "gs = git status
+-- a comment
+dp = <<END
+  docker compose build
+  docker compose up -d
+END
+gl = git log",
+    )
+    .unwrap();
+
+    assert_eq!(
+        combos,
+        vec![
+            ("gs".into(), "git status".into()),
+            (
+                "dp".into(),
+                "docker compose build\ndocker compose up -d".into()
+            ),
+            ("gl".into(), "git log".into()),
+        ]
+    );
+
+    assert!(parse_combos("dp = <<END\ndocker compose up").is_err());
+}
+
+/// Parses all the combos in the text form, including multi-line commands which use the
+/// heredoc-like syntax:
+///
+/// ```text
+/// dp = <<END
+/// docker compose build
+/// docker compose up -d
+/// END
+/// ```
+pub(crate) fn parse_combos(text: &str) -> anyhow::Result<Vec<(String, String)>> {
+    let mut out = vec![];
+    let mut lines = text.lines();
+
+    while let Some(line) = lines.next() {
+        let Some((combo, command)) = parse_combo(line)? else {
+            continue;
+        };
+
+        if let Some(captures) = REGEX_LEADER_HEREDOC.captures(&command) {
+            let prefix = captures.get(1).map_or("", |m| m.as_str());
+            let tag = captures.get(2).unwrap().as_str();
+            let mut body = vec![];
+
+            loop {
+                match lines.next() {
+                    Some(l) if l.trim() == tag => break,
+                    Some(l) => body.push(l),
+                    None => anyhow::bail!(
+                        "Missing the closing {tag:?} for the multi-line command in leader combo: {combo:?}"
+                    ),
+                }
+            }
+            out.push((combo, format!("{prefix}{}", dedent(&body))));
+        } else {
+            out.push((combo, command));
+        }
+    }
+
+    Ok(out)
+}
+
+/// The closing tag, if the command of a combo starts a multi-line command.
+pub(crate) fn heredoc_tag(command: &str) -> Option<&str> {
+    REGEX_LEADER_HEREDOC
+        .captures(command)
+        .and_then(|captures| captures.get(2))
+        .map(|tag| tag.as_str())
+}
+
+/// Removes the indentation which is common to all non-empty lines.
+fn dedent(lines: &[&str]) -> String {
+    let indent = lines
+        .iter()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.len() - l.trim_start().len())
+        .min()
+        .unwrap_or(0);
+
+    lines
+        .iter()
+        .map(|l| l.get(indent..).unwrap_or("").trim_end())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Parses a single line of the text form of the combos.
+///
+/// The line is split on the first '=' which is not escaped (as '\=') or inside angle brackets,
+/// so the command may contain any number of '=' characters.
 pub(crate) fn parse_combo(line: &str) -> Result<Option<(String, String)>, anyhow::Error> {
-    if REGEX_LEADER_COMMENT.is_match(line) || line.is_empty() {
+    if REGEX_LEADER_COMMENT.is_match(line) || line.trim().is_empty() {
         return Ok(None);
     }
 
-    if let Some(captures) = REGEX_LEADER_COMBO.captures(line) {
-        if captures.len() != 3 {
-            return Err(anyhow::anyhow!(
-                "Bad format of string in leader combo: {line:?}"
-            ));
+    let mut escaped = false;
+    let mut in_brackets = false;
+    let mut separator = None;
+
+    for (i, c) in line.char_indices() {
+        match c {
+            '\\' if !escaped => {
+                escaped = true;
+                continue;
+            }
+            // A key in angle brackets has no spaces, so a '<' without a '>' before the next
+            // space is the '<' key.
+            '<' if !escaped => {
+                in_brackets = line[i + 1..]
+                    .split(char::is_whitespace)
+                    .next()
+                    .is_some_and(|word| word.contains('>'))
+            }
+            '>' if !escaped => in_brackets = false,
+            '=' if !escaped && !in_brackets => {
+                separator = Some(i);
+                break;
+            }
+            _ => (),
         }
+        escaped = false;
+    }
 
-        let combo = captures.get(1).unwrap().as_str().to_string();
-        let command = captures.get(2).unwrap().as_str().to_string();
+    let Some(separator) = separator else {
+        anyhow::bail!("Bad format of string in leader combo: {line:?}");
+    };
 
-        for c in REGEX_COMBO_VALIDATE.captures_iter(&combo) {
-            for _match in c.iter().flatten() {
-                let s = _match.as_str();
-                let s = &s[1..s.len() - 1];
+    let combo = line[..separator].trim().replace(r"\=", "=");
+    let command = line[separator + 1..].trim().to_string();
 
-                if !REGEX_CTRL.is_match(s) && !REGEX_ALT.is_match(s) {
-                    anyhow::bail!(
-                        "Bad format of angle brackets in leader combo: {combo:?} (s={s:?})"
-                    );
-                }
-            }
-        }
+    validate_combo_keys(&combo)?;
 
-        Ok(Some((combo, command)))
-    } else {
-        Err(anyhow::anyhow!(
-            "Bad format of string in leader combo: {line:?}"
-        ))
-    }
+    Ok(Some((combo, command)))
+}
+
+/// Checks that the keys of a combo are valid, e.g. that any angle brackets contain a supported key.
+pub(crate) fn validate_combo_keys(combo: &str) -> anyhow::Result<()> {
+    parse_key_sequence(combo).map(|_| ())
 }
@@ -1,23 +1,111 @@
-use std::{path::PathBuf, sync::Mutex};
+use std::{
+    hash::Hasher,
+    os::unix::{ffi::OsStrExt, fs::MetadataExt},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
 
-use once_cell::sync::Lazy;
+use once_cell::sync::{Lazy, OnceCell};
 
+pub mod aliases;
+pub mod cache;
+pub mod cheatsheet;
+pub mod conflicts;
+pub mod edit;
 pub mod keys;
 pub mod nodes;
+pub mod protocol;
+pub mod resolve;
 pub mod shell;
+pub mod stats;
 pub mod tui;
+pub mod vars;
 pub mod yml;
 
 pub const CONFIG_FILE_NAME: &str = ".blz.yml";
 pub const NU_SOURCE_NAME: &str = ".leader_keys.nu";
+pub const CACHE_FILE_NAME: &str = ".cache.json";
 
+pub const PANIC_FILE_NAME: &str = "panic.blz";
+pub const LOG_FILE_NAME: &str = "blz.log";
+pub const STATS_FILE_NAME: &str = "stats.json";
+
+/// The env var which overrides the directory of the global config.
+pub const CONFIG_DIR_VAR: &str = "BLZ_CONFIG_DIR";
+
+/// The directory given with '--config', which takes precedence over the environment. It must be
+/// set before [`CONFIG_DIR`] is first used.
+pub static CONFIG_DIR_ARG: OnceCell<PathBuf> = OnceCell::new();
+
+/// A base directory from the XDG spec, which is ignored unless it's an absolute path.
+fn xdg_dir(var: &str, default: &str) -> PathBuf {
+    match std::env::var_os(var).map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => dir,
+        _ => shellexpand::tilde(default).to_string().into(),
+    }
+}
+
+/// The directory of the global config: from '--config', `BLZ_CONFIG_DIR`, or
+/// `$XDG_CONFIG_HOME/blaze-keys` (by default `~/.config/blaze-keys`).
 pub static CONFIG_DIR: Lazy<PathBuf> = Lazy::new(|| {
-    shellexpand::tilde("~/.config/blaze-keys")
-        .to_string()
-        .into()
+    if let Some(dir) = CONFIG_DIR_ARG.get() {
+        return dir.clone();
+    }
+    match std::env::var(CONFIG_DIR_VAR) {
+        Ok(dir) if !dir.is_empty() => shellexpand::tilde(&dir).to_string().into(),
+        _ => xdg_dir("XDG_CONFIG_HOME", "~/.config").join("blaze-keys"),
+    }
 });
 
-#[derive(PartialEq, Copy, Clone)]
+/// The directory of the files which blz writes for itself, e.g. the log and the last panic:
+/// `$XDG_STATE_HOME/blaze-keys` (by default `~/.local/state/blaze-keys`).
+pub static STATE_DIR: Lazy<PathBuf> =
+    Lazy::new(|| xdg_dir("XDG_STATE_HOME", "~/.local/state").join("blaze-keys"));
+
+/// The directory for short-lived files, such as the replies of the TUI to the shell.
+pub fn runtime_dir() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => dir,
+        _ => std::env::temp_dir(),
+    }
+}
+
+/// The directory of the daemon's socket, which only the user may access:
+/// `$XDG_RUNTIME_DIR/blaze-keys`, or `/tmp/blaze-keys-<uid>`.
+pub fn daemon_socket_dir() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => dir.join("blaze-keys"),
+        _ => std::env::temp_dir().join(format!("blaze-keys-{}", unsafe { libc::geteuid() })),
+    }
+}
+
+/// The socket of the optional daemon, which keeps the parsed config in memory. Each config
+/// directory has its own daemon, so a shell using '--config' or `BLZ_CONFIG_DIR` never gets the
+/// keybinds of another config.
+pub fn daemon_socket_path() -> PathBuf {
+    let config_dir = std::fs::canonicalize(&*CONFIG_DIR).unwrap_or_else(|_| CONFIG_DIR.clone());
+
+    let mut hasher = fnv::FnvHasher::default();
+    hasher.write(config_dir.as_os_str().as_bytes());
+    daemon_socket_dir().join(format!("blz-{:x}.sock", hasher.finish()))
+}
+
+/// Whether the directory belongs to the current user, and no one else may access it.
+pub fn is_private_dir(dir: &Path) -> bool {
+    std::fs::symlink_metadata(dir).is_ok_and(|meta| {
+        meta.is_dir() && meta.uid() == unsafe { libc::geteuid() } && meta.mode() & 0o077 == 0
+    })
+}
+
+/// Whether the socket belongs to the current user, in a private directory. Otherwise another user
+/// could have created it, and would control the code which the shell runs.
+pub fn is_trusted_socket(path: &Path) -> bool {
+    path.parent().is_some_and(is_private_dir)
+        && std::fs::symlink_metadata(path)
+            .is_ok_and(|meta| meta.uid() == unsafe { libc::geteuid() })
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
 pub enum Shell {
     Zsh,
     Nu,
@@ -25,6 +113,10 @@ pub enum Shell {
 
 pub static SHELL: Mutex<Shell> = Mutex::new(Shell::Zsh);
 
+pub fn shell() -> Shell {
+    *SHELL.lock().unwrap()
+}
+
 pub fn is_nushell() -> bool {
-    *SHELL.lock().unwrap() == Shell::Nu
+    shell() == Shell::Nu
 }
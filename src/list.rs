@@ -0,0 +1,83 @@
+//! 'blz list', which shows everything that is bound in the current directory and where it was
+//! defined.
+
+use anyhow::Result;
+use blaze_keys::{
+    resolve::{self, ResolvedKeybind, ResolvedLeader},
+    yml::GlobalConfig,
+};
+use colored::Colorize;
+use serde::Serialize;
+
+use crate::cli::ListFormat;
+
+#[derive(Serialize)]
+struct Listing<'a> {
+    keybinds: &'a [ResolvedKeybind],
+    leaders: &'a [ResolvedLeader],
+}
+
+fn print_human(keybinds: &[ResolvedKeybind], leaders: &[ResolvedLeader]) {
+    println!("{}", "Keybinds".bold());
+
+    let width = keybinds
+        .iter()
+        .map(|r| r.keybind.key.len())
+        .max()
+        .unwrap_or(0);
+
+    for r in keybinds {
+        let target = r.keybind.target();
+        println!(
+            "  {:<width$}  {target:<30}  {}",
+            r.keybind.key,
+            r.origin.to_string().dimmed()
+        );
+    }
+
+    for leader in leaders {
+        println!(
+            "\n{} (exec: {}, abbr: {})",
+            leader.name.bold(),
+            leader.exec_mode,
+            leader.abbr_mode
+        );
+
+        let width = leader
+            .combos
+            .iter()
+            .map(|r| r.combo.keys.len())
+            .max()
+            .unwrap_or(0);
+
+        for r in &leader.combos {
+            let mut line = format!("  {:<width$}  {}", r.combo.keys, r.combo.command);
+
+            if let Some(ref description) = r.combo.description {
+                line += &format!(" -- {description}").dimmed().to_string();
+            }
+            println!("{line}");
+        }
+    }
+}
+
+pub fn run(
+    keybinds: &[ResolvedKeybind],
+    global: &Option<GlobalConfig>,
+    format: ListFormat,
+) -> Result<()> {
+    let leaders = resolve::resolve_leaders(global)?;
+
+    match format {
+        ListFormat::Human => print_human(keybinds, &leaders),
+        ListFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&Listing {
+                keybinds,
+                leaders: &leaders,
+            })?
+        ),
+    }
+
+    Ok(())
+}
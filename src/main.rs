@@ -1,6 +1,14 @@
+mod capture;
+mod check;
 mod cli;
+mod config_edit;
 mod configs;
+mod daemon;
+mod doctor;
+mod import;
+mod list;
 mod panic;
+mod usage;
 
 #[cfg(debug_assertions)]
 mod dev;
@@ -8,27 +16,31 @@ mod dev;
 extern crate termion;
 
 use anyhow::{Result, anyhow};
-use blaze_keys::keys::print_bindkey_zsh;
+use blaze_keys::cheatsheet::Sheet;
+use blaze_keys::conflicts;
+use blaze_keys::edit::Action;
+use blaze_keys::resolve::{self, ResolvedKeybind};
+use blaze_keys::vars::Vars;
+use blaze_keys::yml::GlobalConfig;
 use blaze_keys::yml::{self};
-use blaze_keys::{CONFIG_DIR, shell};
-use blaze_keys::{
-    CONFIG_FILE_NAME, keys::print_human_keys, nodes::Node, shell::nu_hook, shell::zsh_hook,
-};
-use blaze_keys::{SHELL, Shell, keys};
+use blaze_keys::{CONFIG_DIR, CONFIG_DIR_ARG, LOG_FILE_NAME, STATE_DIR, shell};
+use blaze_keys::{CONFIG_FILE_NAME, nodes::Node, shell::nu_hook, shell::zsh_hook};
+use blaze_keys::{SHELL, Shell, is_nushell, keys};
 use clap::Parser;
 use colored::Colorize;
 use flexi_logger::{FileSpec, LoggerHandle};
 use log::debug;
 use std::io::stdin;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use termion::raw::IntoRawMode;
 use termion::screen::IntoAlternateScreen;
 
-use crate::cli::{Args, Porcelain, PorcelainWrapper};
+use crate::cli::{Args, CheatsheetFormat, Command, Porcelain};
 
 fn setup_logging() -> Option<LoggerHandle> {
     if let Ok(blz_log) = std::env::var("BLZ_LOG") {
-        let file_spec = FileSpec::try_from("/tmp/blz.log").unwrap();
+        let _ = std::fs::create_dir_all(STATE_DIR.as_path());
+        let file_spec = FileSpec::try_from(STATE_DIR.join(LOG_FILE_NAME)).unwrap();
 
         let logger = flexi_logger::Logger::try_with_str(&blz_log)
             .unwrap()
@@ -61,9 +73,29 @@ fn check_root() {
     }
 }
 
-fn leader_keys_tui(leader_keys: Node, abbr: bool, tmp: &str) {
+/// Whether the zsh widgets need to initialise the hook again after the TUI exits, because the
+/// global config has changed. Nushell can't do this, so the nu source is regenerated instead.
+fn needs_reload(state: &str) -> bool {
+    !is_nushell() && !shell::check_env_state(state).unwrap_or(true)
+}
+
+fn leader_keys_tui(
+    leader: &str,
+    leader_keys: Node,
+    abbr: bool,
+    tmp: &str,
+    reload: bool,
+    read_buffer: bool,
+) {
     let stdin = stdin();
 
+    // The file is emptied, so that the command line is never mistaken for the reply.
+    let line = match read_buffer {
+        true => std::fs::read_to_string(tmp).unwrap_or_default(),
+        false => String::new(),
+    };
+    let _ = std::fs::write(tmp, "");
+
     let tty = termion::get_tty().unwrap();
 
     let term = tty
@@ -72,14 +104,34 @@ fn leader_keys_tui(leader_keys: Node, abbr: bool, tmp: &str) {
         .into_alternate_screen()
         .unwrap();
 
-    let tui = blaze_keys::tui::Tui::new(term, tmp.to_string(), &leader_keys, abbr);
+    let tui = blaze_keys::tui::Tui::new(
+        term,
+        tmp.to_string(),
+        &leader_keys,
+        abbr,
+        reload,
+        line,
+        leader.to_string(),
+    );
 
     tui.run(stdin);
 }
 
 fn main() -> Result<(), anyhow::Error> {
     let _logger = setup_logging();
-    check_root();
+    let args = Args::parse();
+
+    if let Some(ref dir) = args.config {
+        let dir = shellexpand::tilde(&dir.to_string_lossy()).to_string();
+        CONFIG_DIR_ARG
+            .set(std::path::absolute(dir)?)
+            .map_err(|_| anyhow!("The config directory was already set"))?;
+    }
+
+    // The doctor reports running as root, rather than refusing to run.
+    if !matches!(args.command, Some(Command::Doctor { .. })) {
+        check_root();
+    }
 
     panic::register_hook();
 
@@ -91,8 +143,6 @@ fn main() -> Result<(), anyhow::Error> {
 
     debug!("Executed in {:?}", std::env::current_dir().unwrap());
 
-    let args = Args::parse();
-
     if porcelain_get_bool!(args, Porcelain::print_nu_source_path) {
         println!("{}", nu_hook::nu_source_location());
         return Ok(());
@@ -119,7 +169,7 @@ fn main() -> Result<(), anyhow::Error> {
     let config_file = CONFIG_DIR.join(CONFIG_FILE_NAME);
 
     if args.edit_global_config {
-        configs::edit_config_file(CONFIG_DIR.to_str().unwrap(), &config_file)?;
+        configs::edit_config_file(&CONFIG_DIR, &config_file)?;
     }
     if args.edit_local_config {
         let path = PathBuf::from(CONFIG_FILE_NAME);
@@ -132,8 +182,59 @@ fn main() -> Result<(), anyhow::Error> {
             "ATTENTION".on_cyan(),
             ": You will need to run 'cd .' to refresh the local keybinds.".bright_red()
         );
-        configs::edit_config_file(".", &path)?;
+        configs::edit_config_file(Path::new("."), &path)?;
     }
+    if porcelain_get_bool!(args, Porcelain::daemon) {
+        return daemon::run(config_file);
+    }
+    if let Some(key) = porcelain_get!(args, Porcelain::record_keybind { key } => key) {
+        return blaze_keys::stats::record_keybind(key);
+    }
+
+    // The daemon has already parsed the config, if it's running.
+    if let Some((leader, tmpfile, abbr, read_buffer)) = porcelain_get!(args, Porcelain::leader_key {leader, tmpfile, abbr, read_buffer} => (leader, tmpfile, abbr, read_buffer))
+        && let Some(reply) = daemon::query_leader(leader)
+    {
+        let (leader_keys, state) = reply?;
+        leader_keys_tui(
+            leader,
+            leader_keys,
+            *abbr,
+            tmpfile,
+            needs_reload(&state),
+            *read_buffer,
+        );
+        return Ok(());
+    }
+
+    // The config is edited as text, so it doesn't need to be parsed first, and the doctor reports
+    // any errors in it.
+    match args.command {
+        Some(Command::Add { ref item }) => return config_edit::add(&config_file, item),
+        Some(Command::Remove { ref item }) => return config_edit::remove(&config_file, item),
+        Some(Command::Check { ref bindkey }) => {
+            return check::run(&config_file, bindkey.as_deref());
+        }
+        Some(Command::Doctor { keys: true, .. }) | Some(Command::CaptureKey { bind: None, .. }) => {
+            return capture::run(&configs::parse_global_keybinds(&config_file).transpose()?);
+        }
+        Some(Command::CaptureKey {
+            bind: Some(ref target),
+            zle,
+            ref scope,
+        }) => {
+            let action = match zle {
+                true => Action::Zle(target.clone()),
+                false => Action::Command(target.clone()),
+            };
+            return capture::bind(&config_file, action, scope);
+        }
+        Some(Command::Doctor { ref bindkey, .. }) => {
+            return doctor::run(&config_file, bindkey.as_deref());
+        }
+        _ => (),
+    }
+
     let global_binds = configs::parse_global_keybinds(&config_file).transpose()?;
 
     if args.zsh_hook {
@@ -144,95 +245,209 @@ fn main() -> Result<(), anyhow::Error> {
         nu_hook::generate_nu_source(&global_binds)?;
         return Ok(());
     }
-    let local_binds = configs::parse_local_keybinds();
-
     debug!("Global keybinds: {global_binds:?}");
-    debug!("Loaded local keybinds: {local_binds:?}");
-
-    let ld = match &global_binds {
-        Some(i) => match i.global {
-            Some(ref i) => &i.leader_keys.as_ref(),
-            _ => &None,
-        },
-        None => &None,
-    };
 
     if porcelain_get_bool!(args, Porcelain::print_leader_state) {
-        shell::print_leader_state(ld);
+        shell::print_leader_state(&global_binds);
         return Ok(());
     }
 
-    if !args.porcelain.as_ref().is_some_and(|it| match it {
-        PorcelainWrapper::Porcelain {
+    if let Some(Command::List { format }) = args.command {
+        return list::run(&resolve_for_cwd(&global_binds)?, &global_binds, format);
+    }
+
+    if let Some(Command::Stats {
+        enable,
+        disable,
+        reset,
+        top,
+    }) = args.command
+    {
+        return usage::run(
+            &global_binds,
+            &resolve_for_cwd(&global_binds)?,
+            enable,
+            disable,
+            reset,
+            top,
+        );
+    }
+
+    if let Some(Command::Import {
+        source,
+        ref file,
+        ref leader,
+        write,
+        ref exec_mode,
+        ref abbr_mode,
+    }) = args.command
+    {
+        return import::run(
+            &config_file,
+            &global_binds,
+            source,
+            file.as_deref(),
+            leader,
+            write,
+            exec_mode.as_deref(),
+            abbr_mode.as_deref(),
+        );
+    }
+
+    if let Some(Command::Export { format, profiles }) = args.command {
+        let sheet = match profiles {
+            true => Sheet::new(
+                &resolve_for_cwd(&global_binds)?,
+                &global_binds,
+                &vars_for_cwd(&global_binds)?,
+            )?,
+            false => Sheet::new(
+                &resolve::resolve_global_keybinds(&global_binds),
+                &global_binds,
+                &resolve::resolve_global_vars(&global_binds),
+            )?,
+        };
+
+        print!(
+            "{}",
+            match format {
+                CheatsheetFormat::Markdown => sheet.to_markdown(),
+                CheatsheetFormat::Html => sheet.to_html(),
+                CheatsheetFormat::Svg => sheet.to_svg(),
+                CheatsheetFormat::Text => sheet.to_text(),
+            }
+        );
+        return Ok(());
+    }
+
+    let state = shell::config_state(&global_binds);
+
+    if let Some((leader, tmpfile, abbr, read_buffer)) = porcelain_get!(args, Porcelain::leader_key {leader, tmpfile, abbr, read_buffer} => (leader, tmpfile, abbr, read_buffer))
+    {
+        let leader_keys = Node::root(
+            &global_binds,
+            leader.to_owned(),
+            &vars_for_cwd(&global_binds)?,
+        )?;
+
+        leader_keys_tui(
+            leader,
+            leader_keys,
+            *abbr,
+            tmpfile,
+            needs_reload(&state),
+            *read_buffer,
+        );
+        return Ok(());
+    }
+
+    // Whether the shell was initialised with an older version of the global config.
+    let stale = !args.command.as_ref().is_some_and(|it| match it {
+        Command::Porcelain {
             ignore_leader_state,
             ..
         } => *ignore_leader_state,
-    }) && !porcelain_get_bool!(
-        args,
-        Porcelain::leader_key {
-            leader: _,
-            tmpfile: _,
-            abbr: _
-        }
-    )
-    /* No need to check when the leader key is set, because that means this leader key is up to date at least */
-    {
-        shell::check_leaders(ld)?;
-
-        if porcelain_get_bool!(args, Porcelain::check_leader_state) {
-            return Ok(());
+        _ => false,
+    }) && !shell::check_env_state(&state)?;
+
+    if porcelain_get_bool!(args, Porcelain::check_leader_state) {
+        // A nu session can't reinitialise itself, but new sessions will pick up the new file.
+        if stale && is_nushell() && nu_hook::generate_nu_source(&global_binds)? {
+            eprintln!(
+                "blz: The leader keys have been regenerated. Changes to the trigger keys will apply in new shells."
+            );
         }
+        return Ok(());
     }
 
-    if let Some((leader, tmpfile, abbr)) = porcelain_get!(args, Porcelain::leader_key {leader, tmpfile, abbr} => (leader, tmpfile, abbr))
-    {
-        let leader_keys = Node::root(&global_binds, leader.to_owned())?;
+    if args.show_keybinds {
+        show_keybinds(&global_binds)?;
+
+        if args.conflicts {
+            let bound = conflicts::bound_keys(&resolve_for_cwd(&global_binds)?, &global_binds);
+            let mut found = conflicts::layer_conflicts(&bound);
+            match check::zsh_conflicts(&bound, None) {
+                Ok(zsh) => found.extend(zsh),
+                Err(e) => eprintln!("{}: Skipped the zsh bindings: {e:#}", "WARNING".yellow()),
+            }
 
-        leader_keys_tui(leader_keys, *abbr, tmpfile);
+            for conflict in found {
+                eprintln!("{}: {conflict}", "WARNING".yellow());
+            }
+        }
         return Ok(());
     }
 
-    let emitter = if args.show_keybinds {
-        print_human_keys
-    } else {
-        print_bindkey_zsh
-    };
+    let applied = porcelain_get!(args, Porcelain::blat { applied } => applied.as_deref()).flatten();
 
-    if let Some(ref global_binds) = global_binds {
-        global_binds.emit(&emitter)?;
+    if stale {
+        println!("{}", zsh_hook::RELOAD);
+    } else {
+        print!("{}", blat(&global_binds, applied)?);
     }
 
-    if let Some(binds) = local_binds {
-        let binds = binds?;
+    Ok(())
+}
+
+/// Resolves the keybinds which apply to the current directory, from the global config (including
+/// profiles) and the local config.
+pub(crate) fn resolve_for_cwd(global_binds: &Option<GlobalConfig>) -> Result<Vec<ResolvedKeybind>> {
+    let local_binds = configs::parse_local_keybinds().transpose()?;
+    debug!("Loaded local keybinds: {local_binds:?}");
 
-        if let Some(inherits_profiles) = binds.inherits {
-            let profiles = global_binds
-                .ok_or_else(|| anyhow!("Error: can only use 'inherits' in local config if profiles are defined in global.blz.yml, but the latter seems to be absent"))?
-                .profiles
-                .ok_or_else(|| anyhow!("Error: can only use 'inherits' in local config if profiles are defined in global.blz.yml, but profiles seem to be absent in the latter"))?;
+    let path = std::env::current_dir()?.join(CONFIG_FILE_NAME);
 
-            let profiles: fnv::FnvHashMap<&str, &yml::Profile> = profiles
-                .iter()
-                .map(|profile| (profile.name.as_str(), profile))
-                .collect();
+    resolve::resolve_keybinds(
+        global_binds,
+        local_binds.as_ref().map(|l| (path.as_path(), l)),
+    )
+}
 
-            for p in inherits_profiles {
-                let prof = profiles.get(p.as_str()).ok_or_else(|| anyhow!("Error: Local config inherits profile {p:?} which does not exist in global config"))?;
+/// Resolves the variables which apply to the current directory (see [`resolve::resolve_vars`]).
+pub(crate) fn vars_for_cwd(global_binds: &Option<GlobalConfig>) -> Result<Vars> {
+    let local_binds = configs::parse_local_keybinds().transpose()?;
+    let path = std::env::current_dir()?.join(CONFIG_FILE_NAME);
 
-                debug!("Inherit profile {p:?}");
-                if let Some(ref kb) = prof.keybinds {
-                    keys::emit_keybinds(kb, &emitter)?;
-                }
-            }
-        }
+    resolve::resolve_vars(
+        global_binds,
+        local_binds.as_ref().map(|l| (path.as_path(), l)),
+    )
+}
 
-        if let Some(keybinds) = binds.keybinds {
-            debug!("Emit keybinds from local config");
-            keys::emit_keybinds(&keybinds, &emitter)?;
+/// Shows the keybinds which apply to the current directory, where each one was defined, and the
+/// keybinds for the same key which it shadowed.
+fn show_keybinds(global_binds: &Option<GlobalConfig>) -> Result<()> {
+    for resolved in resolve_for_cwd(global_binds)? {
+        keys::emit_keybind(&resolved.keybind, &|keybind, key_raw, command, zle| {
+            let line = keys::format_human_keys(keybind, key_raw, command, zle)?;
+            println!("{line:<40}  {}", format!("[{}]", resolved.origin).dimmed());
+            Ok(())
+        })?;
+
+        for shadowed in &resolved.shadowed {
+            println!(
+                "{}",
+                format!(
+                    "        shadows '{}' [{}]",
+                    shadowed.keybind.target(),
+                    shadowed.origin
+                )
+                .dimmed()
+            );
         }
-    } else {
-        debug!("No local keybinds found");
     }
 
     Ok(())
 }
+
+/// The output of 'blz porcelain blat', which is sourced by the zsh hook to apply the keybinds (see
+/// [`keys::blat_zsh`]).
+fn blat(global_binds: &Option<GlobalConfig>, applied: Option<&str>) -> Result<String> {
+    let keybinds: Vec<yml::Keybind> = resolve_for_cwd(global_binds)?
+        .into_iter()
+        .map(|resolved| resolved.keybind)
+        .collect();
+    let record = blaze_keys::stats::Stats::read().is_some();
+
+    keys::blat_zsh(&keybinds, applied, record)
+}
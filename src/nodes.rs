@@ -1,26 +1,89 @@
 use log::debug;
 use once_cell::sync::Lazy;
 use regex::Regex;
-use std::ops::Deref;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{cmp::Ordering, collections::BTreeSet};
+use termion::event::Key;
 
 use crate::{
-    keys::{self},
-    yml::GlobalConfig,
+    vars::{self, Vars},
+    yml::{Combo, ComboMode, Combos, GlobalConfig},
 };
 
-static CTRL_ALT_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"(<(?:Ctrl|Alt|C|A)-.>|.)").unwrap());
+static KEY_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"(<[^<>]+>|.)").unwrap());
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct Node {
+    #[serde(with = "children_as_list")]
     pub children: fnv::FnvHashMap<CharWithModifiers, Node>,
     pub command: Option<String>,
+    pub description: Option<String>,
+    /// Overrides the mode of the leader key which triggered the TUI.
+    pub mode: Option<ComboMode>,
+    /// The placeholders in the command which have no value in the current directory, in which
+    /// case the combo can't be used.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unresolved: Vec<String>,
 }
 
-#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
 pub enum CharWithModifiers {
     Ctrl(char),
     Alt(char),
     Unmodified(char),
+    /// A key which doesn't produce a printable char, such as `<Tab>` or `<S-Left>`.
+    Named(NamedKey, Modifier),
+}
+
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+pub enum NamedKey {
+    Tab,
+    Enter,
+    Space,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Delete,
+    Insert,
+    F(u8),
+}
+
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+pub enum Modifier {
+    None,
+    Shift,
+    Ctrl,
+    Alt,
+}
+
+/// Serializes the children as a list, since the keys can't be represented as strings in JSON.
+mod children_as_list {
+    use super::*;
+
+    pub fn serialize<S>(
+        children: &fnv::FnvHashMap<CharWithModifiers, Node>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(children.iter())
+    }
+
+    pub fn deserialize<'de, D>(
+        deserializer: D,
+    ) -> Result<fnv::FnvHashMap<CharWithModifiers, Node>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let children: Vec<(CharWithModifiers, Node)> = Vec::deserialize(deserializer)?;
+        Ok(children.into_iter().collect())
+    }
 }
 
 #[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
@@ -29,25 +92,95 @@ pub struct CharWithModifiersAndValidity {
     pub valid: bool,
 }
 
-impl Deref for CharWithModifiers {
-    type Target = char;
+impl NamedKey {
+    fn from_name(name: &str) -> Option<NamedKey> {
+        let key = match name.to_ascii_lowercase().as_str() {
+            "tab" => NamedKey::Tab,
+            "enter" | "return" | "cr" => NamedKey::Enter,
+            "space" => NamedKey::Space,
+            "left" => NamedKey::Left,
+            "right" => NamedKey::Right,
+            "up" => NamedKey::Up,
+            "down" => NamedKey::Down,
+            "home" => NamedKey::Home,
+            "end" => NamedKey::End,
+            "pageup" => NamedKey::PageUp,
+            "pagedown" => NamedKey::PageDown,
+            "del" | "delete" => NamedKey::Delete,
+            "insert" => NamedKey::Insert,
+            f => match f.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                Some(n @ 1..=12) => NamedKey::F(n),
+                _ => return None,
+            },
+        };
+
+        Some(key)
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            NamedKey::Tab => "Tab".into(),
+            NamedKey::Enter => "Enter".into(),
+            NamedKey::Space => "Space".into(),
+            NamedKey::Left => "Left".into(),
+            NamedKey::Right => "Right".into(),
+            NamedKey::Up => "Up".into(),
+            NamedKey::Down => "Down".into(),
+            NamedKey::Home => "Home".into(),
+            NamedKey::End => "End".into(),
+            NamedKey::PageUp => "PageUp".into(),
+            NamedKey::PageDown => "PageDown".into(),
+            NamedKey::Delete => "Delete".into(),
+            NamedKey::Insert => "Insert".into(),
+            NamedKey::F(n) => format!("F{n}"),
+        }
+    }
 
-    fn deref(&self) -> &Self::Target {
-        self.bare()
+    /// Whether the terminal can report this key with the modifier (as far as termion is concerned).
+    fn supports(&self, modifier: Modifier) -> bool {
+        match modifier {
+            Modifier::None => true,
+            Modifier::Shift => matches!(
+                self,
+                NamedKey::Tab | NamedKey::Left | NamedKey::Right | NamedKey::Up | NamedKey::Down
+            ),
+            Modifier::Ctrl => matches!(
+                self,
+                NamedKey::Left
+                    | NamedKey::Right
+                    | NamedKey::Up
+                    | NamedKey::Down
+                    | NamedKey::Home
+                    | NamedKey::End
+            ),
+            Modifier::Alt => matches!(
+                self,
+                NamedKey::Left | NamedKey::Right | NamedKey::Up | NamedKey::Down
+            ),
+        }
     }
 }
 
 impl CharWithModifiers {
-    pub fn bare(&self) -> &char {
+    /// The char, if this is not a named key.
+    pub fn bare(&self) -> Option<char> {
         match self {
-            Self::Ctrl(c) | Self::Alt(c) | Self::Unmodified(c) => c,
+            Self::Ctrl(c) | Self::Alt(c) | Self::Unmodified(c) => Some(*c),
+            Self::Named(..) => None,
         }
     }
+
     pub fn str_short(&self) -> String {
         match self {
             Self::Ctrl(c) => format!("<C-{c}>"),
             Self::Alt(c) => format!("<A-{c}>"),
             Self::Unmodified(c) => format!("{c}"),
+            Self::Named(key, modifier) => match modifier {
+                Modifier::None => format!("<{}>", key.name()),
+                Modifier::Shift => format!("<S-{}>", key.name()),
+                Modifier::Ctrl => format!("<C-{}>", key.name()),
+                Modifier::Alt => format!("<A-{}>", key.name()),
+            },
         }
     }
 
@@ -56,70 +189,199 @@ impl CharWithModifiers {
         match self {
             Self::Ctrl(_) | Self::Alt(_) => 5,
             Self::Unmodified(_) => 1,
+            Self::Named(..) => self.str_short().len(),
         }
     }
+
+    /// Converts a key read by termion, if it can be used in a combo.
+    pub fn from_key(key: Key) -> Option<CharWithModifiers> {
+        use CharWithModifiers::Named;
+
+        let key = match key {
+            Key::Char('\t') => Named(NamedKey::Tab, Modifier::None),
+            Key::BackTab => Named(NamedKey::Tab, Modifier::Shift),
+            Key::Char('\n') | Key::Ctrl('M') => Named(NamedKey::Enter, Modifier::None),
+            Key::Char(' ') => Named(NamedKey::Space, Modifier::None),
+            Key::Char(c) => CharWithModifiers::Unmodified(c),
+            Key::Ctrl(c) => CharWithModifiers::Ctrl(c),
+            Key::Alt(c) => CharWithModifiers::Alt(c),
+            Key::Left => Named(NamedKey::Left, Modifier::None),
+            Key::ShiftLeft => Named(NamedKey::Left, Modifier::Shift),
+            Key::AltLeft => Named(NamedKey::Left, Modifier::Alt),
+            Key::CtrlLeft => Named(NamedKey::Left, Modifier::Ctrl),
+            Key::Right => Named(NamedKey::Right, Modifier::None),
+            Key::ShiftRight => Named(NamedKey::Right, Modifier::Shift),
+            Key::AltRight => Named(NamedKey::Right, Modifier::Alt),
+            Key::CtrlRight => Named(NamedKey::Right, Modifier::Ctrl),
+            Key::Up => Named(NamedKey::Up, Modifier::None),
+            Key::ShiftUp => Named(NamedKey::Up, Modifier::Shift),
+            Key::AltUp => Named(NamedKey::Up, Modifier::Alt),
+            Key::CtrlUp => Named(NamedKey::Up, Modifier::Ctrl),
+            Key::Down => Named(NamedKey::Down, Modifier::None),
+            Key::ShiftDown => Named(NamedKey::Down, Modifier::Shift),
+            Key::AltDown => Named(NamedKey::Down, Modifier::Alt),
+            Key::CtrlDown => Named(NamedKey::Down, Modifier::Ctrl),
+            Key::Home => Named(NamedKey::Home, Modifier::None),
+            Key::CtrlHome => Named(NamedKey::Home, Modifier::Ctrl),
+            Key::End => Named(NamedKey::End, Modifier::None),
+            Key::CtrlEnd => Named(NamedKey::End, Modifier::Ctrl),
+            Key::PageUp => Named(NamedKey::PageUp, Modifier::None),
+            Key::PageDown => Named(NamedKey::PageDown, Modifier::None),
+            Key::Delete => Named(NamedKey::Delete, Modifier::None),
+            Key::Insert => Named(NamedKey::Insert, Modifier::None),
+            Key::F(n) => Named(NamedKey::F(n), Modifier::None),
+            _ => return None,
+        };
+
+        Some(key)
+    }
+
+    /// Parses the contents of angle brackets in a combo, e.g. `C-s`, `Tab` or `S-Left`.
+    fn from_bracketed(inner: &str) -> Option<CharWithModifiers> {
+        let (modifier, name) = match inner.split_once('-') {
+            Some((m, rest)) if !rest.is_empty() => {
+                let modifier = match m {
+                    "Ctrl" | "C" | "ctrl" => Modifier::Ctrl,
+                    "Alt" | "A" | "alt" => Modifier::Alt,
+                    "Shift" | "S" | "shift" => Modifier::Shift,
+                    _ => return None,
+                };
+                (modifier, rest)
+            }
+            _ => (Modifier::None, inner),
+        };
+
+        let mut chars = name.chars();
+
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            return match modifier {
+                Modifier::Ctrl => Some(CharWithModifiers::Ctrl(c)),
+                Modifier::Alt => Some(CharWithModifiers::Alt(c)),
+                // Shift and a char is just an uppercase char, and '<x>' is just 'x'.
+                Modifier::Shift | Modifier::None => None,
+            };
+        }
+
+        let key = NamedKey::from_name(name)?;
+
+        key.supports(modifier)
+            .then_some(CharWithModifiers::Named(key, modifier))
+    }
 }
 
 impl From<char> for CharWithModifiers {
     fn from(c: char) -> CharWithModifiers {
-        CharWithModifiers::Unmodified(c)
+        match c {
+            ' ' => CharWithModifiers::Named(NamedKey::Space, Modifier::None),
+            c => CharWithModifiers::Unmodified(c),
+        }
+    }
+}
+
+/// Orders keys alphabetically (ignoring case, then lowercase first), with named keys last.
+pub fn compare_keys(a: &CharWithModifiers, b: &CharWithModifiers) -> Ordering {
+    match (a.bare(), b.bare()) {
+        (Some(a), Some(b)) => {
+            let alpha_cmp = a.to_ascii_lowercase().cmp(&b.to_ascii_lowercase());
+
+            if alpha_cmp == Ordering::Equal {
+                a.is_uppercase().cmp(&b.is_uppercase())
+            } else {
+                alpha_cmp
+            }
+        }
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => a.str_short().cmp(&b.str_short()),
+    }
+}
+
+/// Parses the keys of a combo into the sequence of keys which must be pressed, e.g. `g<A-c>g`.
+pub fn parse_key_sequence(combo: &str) -> anyhow::Result<Vec<CharWithModifiers>> {
+    let mut chars: Vec<CharWithModifiers> = vec![];
+
+    for cap in KEY_REGEX.captures_iter(combo) {
+        match cap.get(1) {
+            Some(m) => {
+                let key = m.as_str();
+
+                if key.len() > 1 && key.starts_with('<') {
+                    let inner = &key[1..key.len() - 1];
+
+                    match CharWithModifiers::from_bracketed(inner) {
+                        Some(c) => chars.push(c),
+                        None => anyhow::bail!(
+                            "Bad format of angle brackets in leader combo: {combo:?} (s={inner:?})"
+                        ),
+                    }
+                } else {
+                    chars.push(key.chars().next().unwrap().into());
+                }
+            }
+            _ => anyhow::bail!("Invalid syntax in combo: {combo}"),
+        };
     }
+
+    Ok(chars)
+}
+
+#[test]
+fn test_parse_special_keys() {
+    use CharWithModifiers::*;
+
+    assert_eq!(
+        parse_key_sequence("<F5>bg<Tab><S-Tab><Space><C-Left>").unwrap(),
+        vec![
+            Named(NamedKey::F(5), Modifier::None),
+            Unmodified('b'),
+            Unmodified('g'),
+            Named(NamedKey::Tab, Modifier::None),
+            Named(NamedKey::Tab, Modifier::Shift),
+            Named(NamedKey::Space, Modifier::None),
+            Named(NamedKey::Left, Modifier::Ctrl),
+        ]
+    );
+    assert_eq!(
+        parse_key_sequence("<Ctrl-s><A-x>").unwrap(),
+        vec![Ctrl('s'), Alt('x')]
+    );
+
+    assert!(parse_key_sequence("<F13>").is_err());
+    assert!(parse_key_sequence("<S-F5>").is_err());
+    assert!(parse_key_sequence("<c-s>").is_err());
+    assert!(parse_key_sequence("<x>").is_err());
 }
 
 impl Node {
-    pub fn root(global: &Option<GlobalConfig>, leader_chosen: String) -> anyhow::Result<Self> {
+    /// The tree of combos of a leader key, with the variables of the current directory (see
+    /// [`crate::resolve::resolve_vars`]) interpolated into the commands.
+    pub fn root(
+        global: &Option<GlobalConfig>,
+        leader_chosen: String,
+        vars: &Vars,
+    ) -> anyhow::Result<Self> {
+        let mut root = Node::tree(global, leader_chosen)?;
+        let declared = global.iter().flat_map(|g| g.var_names()).collect();
+        root.interpolate(vars, &declared);
+        Ok(root)
+    }
+
+    /// The tree of combos of a leader key, as it's written in the config.
+    fn tree(global: &Option<GlobalConfig>, leader_chosen: String) -> anyhow::Result<Self> {
         let mut root = Node::default();
 
         if let Some(global) = global
             && let Some(ref conf) = global.global
             && let Some(ref leaderkeys) = conf.leader_keys
         {
+            if let Some(tree) = global.leader_trees.get(&leader_chosen) {
+                debug!("Use the compiled tree for leader {leader_chosen:?}");
+                return Ok(tree.clone());
+            }
+
             for leader_key in leaderkeys {
                 if leader_key.sanitized_name() == leader_chosen {
-                    for line in leader_key.combos.lines() {
-                        match keys::parse_combo(line) {
-                            Ok(Some((combo, command))) => {
-                                debug!("Found leader combo: {combo} -> {command}");
-
-                                let mut chars: Vec<CharWithModifiers> = vec![];
-
-                                for cap in CTRL_ALT_REGEX.captures_iter(&combo) {
-                                    match cap.get(1) {
-                                        Some(m) => {
-                                            let key = m.as_str();
-
-                                            if key.starts_with("<C") {
-                                                let c = key.chars().nth_back(1).unwrap();
-                                                chars.push(CharWithModifiers::Ctrl(c));
-                                            } else if key.starts_with("<A") {
-                                                let c = key.chars().nth_back(1).unwrap();
-                                                chars.push(CharWithModifiers::Alt(c));
-                                            } else {
-                                                chars.push(CharWithModifiers::Unmodified(
-                                                    key.chars().nth(0).unwrap(),
-                                                ));
-                                            }
-                                        }
-                                        _ => anyhow::bail!("Invalid syntax in combo: {combo}"),
-                                    };
-                                }
-                                debug!("new chars: {chars:?}");
-
-                                let mut node = root.find_node(*chars.first().unwrap());
-
-                                for char in chars[1..chars.len()].iter() {
-                                    node = node.find_node(*char);
-                                }
-
-                                if node.command.is_some() {
-                                    anyhow::bail!("combo {combo:?} is defined multiple times");
-                                }
-                                node.command = Some(command);
-                            }
-                            Ok(None) => continue,
-                            Err(e) => return Err(e),
-                        }
-                    }
+                    root = Node::from_combos(&leader_key.combos)?;
                     break;
                 }
             }
@@ -128,6 +390,134 @@ impl Node {
         Ok(root)
     }
 
+    /// Builds the tree of nodes for the combos of a leader key.
+    pub fn from_combos(combos: &Combos) -> anyhow::Result<Self> {
+        let mut root = Node::default();
+
+        for combo in combos.flatten()? {
+            debug!("Found leader combo: {} -> {}", combo.keys, combo.command);
+            root.insert(combo)?;
+        }
+
+        if let Some((keys, _)) = root
+            .commands()
+            .into_iter()
+            .find(|(_, node)| node.select_key().is_none())
+        {
+            anyhow::bail!(
+                "combo {:?} can't be selected, because <Space>, <Enter> and <Tab> all continue it",
+                keys.iter().map(|c| c.str_short()).collect::<String>()
+            );
+        }
+
+        Ok(root)
+    }
+
+    fn insert(&mut self, combo: Combo) -> anyhow::Result<()> {
+        let chars = parse_key_sequence(&combo.keys)?;
+        debug!("new chars: {chars:?}");
+
+        if chars.is_empty() {
+            anyhow::bail!("combo for {:?} has no keys", combo.command);
+        }
+
+        let mut node = self;
+
+        for char in chars {
+            node = node.find_node(char);
+        }
+
+        if node.command.is_some() {
+            anyhow::bail!("combo {:?} is defined multiple times", combo.keys);
+        }
+        node.command = Some(combo.command);
+        node.description = combo.description;
+        node.mode = combo.mode;
+
+        Ok(())
+    }
+
+    /// Interpolates the variables into the commands of this node and those beneath it (see
+    /// [`crate::vars`]), and records the `declared` variables which are missing.
+    fn interpolate(&mut self, vars: &Vars, declared: &BTreeSet<String>) {
+        // Scripts are shell code, and the hooks define them by their text, so they're left as is.
+        if let Some(ref mut command) = self.command
+            && self.mode != Some(ComboMode::Script)
+        {
+            *command = vars::interpolate(command, vars);
+            self.unresolved = vars::unresolved(command, declared);
+        }
+        for child in self.children.values_mut() {
+            child.interpolate(vars, declared);
+        }
+    }
+
+    /// Interpolates the environment variables of this process into the commands of this node and
+    /// those beneath it, for a tree from the daemon, which leaves them to the shell (see
+    /// [`vars::leave_env_to_shell`]).
+    pub fn interpolate_env(&mut self) {
+        if let Some(ref mut command) = self.command
+            && self.mode != Some(ComboMode::Script)
+        {
+            *command = vars::interpolate(command, &Vars::new());
+            let command = &*command;
+            self.unresolved
+                .retain(|name| command.contains(&format!("${{{name}}}")));
+        }
+        for child in self.children.values_mut() {
+            child.interpolate_env();
+        }
+    }
+
+    /// The key which selects the command of this node in the TUI, rather than moving to a child:
+    /// Space, or Enter if Space continues a combo, or Tab if both do.
+    pub fn select_key(&self) -> Option<CharWithModifiers> {
+        [NamedKey::Space, NamedKey::Enter, NamedKey::Tab]
+            .into_iter()
+            .map(|key| CharWithModifiers::Named(key, Modifier::None))
+            .find(|key| !self.children.contains_key(key))
+    }
+
+    /// Whether the command can be used in the current directory, i.e. it has no unresolved
+    /// placeholders.
+    pub fn is_available(&self) -> bool {
+        self.unresolved.is_empty()
+    }
+
+    /// Every node with a command beneath this one, depth-first in the order of [`compare_keys`],
+    /// with the sequence of keys which leads to it.
+    pub fn commands(&self) -> Vec<(Vec<CharWithModifiers>, &Node)> {
+        let mut out = vec![];
+        self.collect_commands(&mut vec![], &mut out);
+        out
+    }
+
+    fn collect_commands<'a>(
+        &'a self,
+        path: &mut Vec<CharWithModifiers>,
+        out: &mut Vec<(Vec<CharWithModifiers>, &'a Node)>,
+    ) {
+        if self.command.is_some() {
+            out.push((path.clone(), self));
+        }
+
+        let mut children: Vec<_> = self.children.iter().collect();
+        children.sort_by(|a, b| compare_keys(a.0, b.0));
+
+        for (key, child) in children {
+            path.push(*key);
+            child.collect_commands(path, out);
+            path.pop();
+        }
+    }
+
+    /// Sets the command at the sequence, for a tree which is only used to look up the combos which
+    /// are taken (see [`crate::stats::is_free`]).
+    pub(crate) fn insert_command(&mut self, sequence: &[CharWithModifiers], command: &str) {
+        let node = sequence.iter().fold(self, |node, key| node.find_node(*key));
+        node.command = Some(command.to_string());
+    }
+
     fn find_node(&mut self, char: CharWithModifiers) -> &mut Node {
         let ch = &mut self.children;
         let node = Node::default();
@@ -135,3 +525,136 @@ impl Node {
         ch.entry(char).or_insert(node)
     }
 }
+
+#[test]
+fn test_combo_forms_build_same_tree() {
+    let text: Combos = serde_yml::from_str(
+        "|
+  gs = git status
+  gl = git log
+  glo = git log --oneline
+  <C-s> = fg
+  gcom = @abbr git commit -m
+  S = @script BUFFER=\"sudo $BUFFER\"
+",
+    )
+    .unwrap();
+    let list: Combos = serde_yml::from_str(
+        "
+- keys: gs
+  command: git status
+- keys: gl
+  command: git log
+- keys: glo
+  command: git log --oneline
+- keys: <C-s>
+  command: fg
+- keys: gcom
+  command: git commit -m
+  mode: abbr
+- keys: S
+  script: BUFFER=\"sudo $BUFFER\"
+",
+    )
+    .unwrap();
+    let tree: Combos = serde_yml::from_str(
+        "
+g:
+  s: git status
+  l:
+    command: git log
+    children:
+      o: git log --oneline
+  com:
+    command: git commit -m
+    mode: abbr
+<C-s>: fg
+S:
+  script: BUFFER=\"sudo $BUFFER\"
+",
+    )
+    .unwrap();
+
+    let text = Node::from_combos(&text).unwrap();
+    assert_eq!(text, Node::from_combos(&list).unwrap());
+    assert_eq!(text, Node::from_combos(&tree).unwrap());
+
+    let both: Combos = serde_yml::from_str("[{keys: S, command: sudo, script: 'true'}]").unwrap();
+    assert!(Node::from_combos(&both).is_err());
+}
+
+#[test]
+fn test_select_key() {
+    use CharWithModifiers::Named;
+
+    let combos: Combos =
+        serde_yml::from_str("|\n  g = git\n  g<Space> = git status\n  g<Enter> = git log\n")
+            .unwrap();
+    let root = Node::from_combos(&combos).unwrap();
+    let g = &root.children[&'g'.into()];
+    assert_eq!(g.select_key(), Some(Named(NamedKey::Tab, Modifier::None)));
+
+    // Nothing could select 'g'.
+    let combos: Combos = serde_yml::from_str(
+        "|\n  g = git\n  g<Space> = git status\n  g<Enter> = git log\n  g<Tab> = git diff\n",
+    )
+    .unwrap();
+    assert!(Node::from_combos(&combos).is_err());
+}
+
+#[test]
+fn test_root_vars() {
+    use CharWithModifiers::*;
+
+    let global: GlobalConfig = serde_yml::from_str(
+        r#"
+global:
+  keybinds: []
+  leader_keys:
+    - name: L
+      exec_mode: Alt-s
+      abbr_mode: Alt-a
+      combos: |
+        b = ${build_cmd}
+        t = ${test_cmd}
+        d = docker compose ${args:-up}
+        s = @script BUFFER="sudo ${BUFFER}"
+        f = for f in *.rs; do wc -l ${f}; done
+profiles:
+  - name: Rust
+    vars:
+      test_cmd: cargo test
+"#,
+    )
+    .unwrap();
+
+    let vars: Vars = [("build_cmd".to_string(), "cargo build".to_string())].into();
+    let root = Node::root(&Some(global), "L".into(), &vars).unwrap();
+
+    let build = &root.children[&Unmodified('b')];
+    assert_eq!(build.command.as_deref(), Some("cargo build"));
+    assert!(build.is_available());
+
+    let test = &root.children[&Unmodified('t')];
+    assert_eq!(test.unresolved, ["test_cmd"]);
+    assert!(!test.is_available());
+
+    let docker = &root.children[&Unmodified('d')];
+    assert_eq!(docker.command.as_deref(), Some("docker compose up"));
+
+    // Scripts are left for the shell.
+    let script = &root.children[&Unmodified('s')];
+    assert_eq!(
+        script.command.as_deref(),
+        Some(r#"BUFFER="sudo ${BUFFER}""#)
+    );
+    assert!(script.is_available());
+
+    // Only the variables which the config declares can be missing; '${f}' is the shell's.
+    let shell_var = &root.children[&Unmodified('f')];
+    assert_eq!(
+        shell_var.command.as_deref(),
+        Some("for f in *.rs; do wc -l ${f}; done")
+    );
+    assert!(shell_var.is_available());
+}
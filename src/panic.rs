@@ -1,11 +1,13 @@
 use std::fs::File;
 use std::io::Write;
 
+use blaze_keys::{PANIC_FILE_NAME, STATE_DIR};
+
 pub fn register_hook() {
     let hook = std::panic::take_hook();
 
     // If we panic during the TUI render, the message may be invisible to the user.
-    // We write the error to a file instead.
+    // We write the error to a file in the state directory instead.
     std::panic::set_hook(Box::new(move |info| {
         let location = info.location().unwrap();
         let message = info.payload().downcast_ref::<&str>();
@@ -16,12 +18,14 @@ pub fn register_hook() {
             "Panic occurred without a message."
         };
 
-        let mut file = File::create(".panic.blz").unwrap();
-        write!(
-            file,
-            "A panic occurred in blz: \n{out:?}\nlocation: {location:?}"
-        )
-        .unwrap();
+        let cwd = std::env::current_dir().unwrap_or_default();
+        let _ = std::fs::create_dir_all(STATE_DIR.as_path());
+        if let Ok(mut file) = File::create(STATE_DIR.join(PANIC_FILE_NAME)) {
+            let _ = write!(
+                file,
+                "A panic occurred in blz: \n{out:?}\nlocation: {location:?}\ndirectory: {cwd:?}"
+            );
+        }
 
         eprintln!("Panicked! (location={location}) \nmessage={message:?}");
         hook(info);
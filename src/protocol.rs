@@ -0,0 +1,246 @@
+//! The result which the TUI writes to the tmpfile, to tell the shell widget what to do.
+//!
+//! The file contains `key=value` lines, then an empty line, then the text (which may span
+//! multiple lines):
+//!
+//! ```text
+//! action=insert
+//! space=1
+//!
+//! git commit -m
+//! ```
+//!
+//! The widgets parse this without `eval`, so new actions only need a new `action` value.
+
+use std::fmt::Display;
+
+use crate::{keys, yml::ComboMode};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Insert the text at the cursor.
+    Insert,
+    /// Insert the text at the cursor, then accept the line.
+    Exec,
+    /// Run the zle widget named by the text (with any arguments separated by whitespace).
+    Zle,
+    /// Replace the current line with the text.
+    Replace,
+    /// Replace the current line with the text, then accept the line.
+    ReplaceExec,
+    /// Run the script of a combo, which the shell hook defined under the name in the text (see
+    /// [`keys::script_name`]).
+    Script,
+    /// Do nothing, because the TUI was cancelled.
+    Cancel,
+}
+
+impl Action {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Insert => "insert",
+            Action::Exec => "exec",
+            Action::Zle => "zle",
+            Action::Script => "script",
+            Action::Replace => "replace",
+            Action::ReplaceExec => "replace-exec",
+            Action::Cancel => "cancel",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Action> {
+        match name {
+            "insert" => Some(Action::Insert),
+            "exec" => Some(Action::Exec),
+            "zle" => Some(Action::Zle),
+            "script" => Some(Action::Script),
+            "replace" => Some(Action::Replace),
+            "replace-exec" => Some(Action::ReplaceExec),
+            "cancel" => Some(Action::Cancel),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reply {
+    pub action: Action,
+    pub text: String,
+    /// Whether a space should be inserted after the text.
+    pub space: bool,
+    /// Where to put the cursor, as an offset in chars from the start of the inserted text.
+    /// The cursor is left after the inserted text if this is absent.
+    pub cursor: Option<usize>,
+    /// Whether the shell hook should be initialised again, since the global config has changed.
+    pub reload: bool,
+}
+
+impl Reply {
+    /// The reply for the command of a combo, in the given mode. `line` is the current command
+    /// line, for the modes which transform it, and `abbr` is whether the leader key was triggered
+    /// in abbr mode, in which case the transformed line isn't accepted.
+    pub fn for_command(command: &str, mode: ComboMode, line: &str, abbr: bool) -> Reply {
+        if matches!(mode, ComboMode::Exec | ComboMode::Abbr | ComboMode::Insert)
+            && let Some(widget) = command.strip_prefix("zle ")
+        {
+            return Reply {
+                action: Action::Zle,
+                text: widget.trim().to_string(),
+                space: false,
+                cursor: None,
+                reload: false,
+            };
+        }
+
+        // With no line to transform, the command is inserted, so it isn't run on its own.
+        if mode.transform(command, line).is_none()
+            && let Some((text, cursor)) = mode.insertion(command)
+        {
+            return Reply {
+                action: Action::Insert,
+                text,
+                space: false,
+                cursor,
+                reload: false,
+            };
+        }
+
+        let (action, space) = match mode {
+            ComboMode::Exec => (Action::Exec, false),
+            ComboMode::Abbr => (Action::Insert, true),
+            ComboMode::Insert => (Action::Insert, false),
+            ComboMode::Script => (Action::Script, false),
+            ComboMode::Prefix | ComboMode::Pipe | ComboMode::Wrap | ComboMode::Replace if abbr => {
+                (Action::Replace, false)
+            }
+            ComboMode::Prefix | ComboMode::Pipe | ComboMode::Wrap | ComboMode::Replace => {
+                (Action::ReplaceExec, false)
+            }
+        };
+        let text = match action {
+            Action::Script => keys::script_name(command),
+            _ => mode
+                .transform(command, line)
+                .unwrap_or_else(|| command.to_string()),
+        };
+
+        Reply {
+            action,
+            text,
+            space,
+            cursor: None,
+            reload: false,
+        }
+    }
+
+    pub fn cancelled() -> Reply {
+        Reply {
+            action: Action::Cancel,
+            text: String::new(),
+            space: false,
+            cursor: None,
+            reload: false,
+        }
+    }
+
+    pub fn parse(content: &str) -> Option<Reply> {
+        let (header, text) = content.split_once("\n\n")?;
+
+        let mut action = None;
+        let mut space = false;
+        let mut cursor = None;
+        let mut reload = false;
+
+        for line in header.lines() {
+            match line.split_once('=')? {
+                ("action", value) => action = Action::from_name(value),
+                ("space", value) => space = value == "1",
+                ("cursor", value) => cursor = value.parse().ok(),
+                ("reload", value) => reload = value == "1",
+                // Ignore anything unknown, so older versions can read newer replies.
+                _ => (),
+            }
+        }
+
+        Some(Reply {
+            action: action?,
+            text: text.to_string(),
+            space,
+            cursor,
+            reload,
+        })
+    }
+}
+
+impl Display for Reply {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "action={}", self.action.name())?;
+        writeln!(f, "space={}", self.space as u8)?;
+
+        if let Some(cursor) = self.cursor {
+            writeln!(f, "cursor={cursor}")?;
+        }
+        if self.reload {
+            writeln!(f, "reload=1")?;
+        }
+
+        write!(f, "\n{}", self.text)
+    }
+}
+
+#[test]
+fn test_reply_round_trip() {
+    let reply = Reply::for_command("git commit -m", ComboMode::Abbr, "", true);
+    assert_eq!(reply.action, Action::Insert);
+    assert!(reply.space);
+    assert_eq!(reply.to_string(), "action=insert\nspace=1\n\ngit commit -m");
+    assert_eq!(Reply::parse(&reply.to_string()), Some(reply));
+
+    let reply = Reply {
+        cursor: Some(3),
+        ..Reply::for_command("echo\n\necho", ComboMode::Exec, "", false)
+    };
+    assert_eq!(Reply::parse(&reply.to_string()), Some(reply));
+
+    let reply = Reply::for_command("zle push-line", ComboMode::Exec, "", false);
+    assert_eq!(reply.action, Action::Zle);
+    assert_eq!(reply.text, "push-line");
+
+    let reply = Reply::for_command("BUFFER=\"sudo $BUFFER\"", ComboMode::Script, "", false);
+    assert_eq!(reply.action, Action::Script);
+    assert!(reply.text.starts_with("_blz_script_"));
+
+    let reply = Reply::for_command("sudo", ComboMode::Prefix, "apt update", false);
+    assert_eq!(reply.action, Action::ReplaceExec);
+    assert_eq!(reply.text, "sudo apt update");
+    assert_eq!(Reply::parse(&reply.to_string()), Some(reply));
+
+    let reply = Reply::for_command("less", ComboMode::Pipe, "git log ", true);
+    assert_eq!(reply.action, Action::Replace);
+    assert_eq!(reply.text, "git log | less");
+
+    let reply = Reply::for_command("echo $({})", ComboMode::Wrap, "date", true);
+    assert_eq!(reply.text, "echo $(date)");
+
+    // An empty line isn't transformed, so the command is inserted rather than run.
+    let reply = Reply::for_command("less", ComboMode::Pipe, "  ", false);
+    assert_eq!(
+        (reply.action, reply.text.as_str()),
+        (Action::Insert, " | less")
+    );
+    assert_eq!(reply.cursor, Some(0));
+    let reply = Reply::for_command("sudo", ComboMode::Prefix, "", false);
+    assert_eq!(
+        (reply.action, reply.text.as_str()),
+        (Action::Insert, "sudo ")
+    );
+    let reply = Reply::for_command("echo $({})", ComboMode::Wrap, "", false);
+    assert_eq!((reply.text.as_str(), reply.cursor), ("echo $()", Some(7)));
+
+    let reply = Reply {
+        reload: true,
+        ..Reply::cancelled()
+    };
+    assert_eq!(reply.to_string(), "action=cancel\nspace=0\nreload=1\n\n");
+    assert_eq!(Reply::parse(&reply.to_string()), Some(reply));
+}
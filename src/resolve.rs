@@ -0,0 +1,689 @@
+//! Resolves the keybinds and leader combos which apply to a directory, and where each one was
+//! defined.
+
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, anyhow, bail};
+use log::{debug, info};
+use serde::Serialize;
+
+use crate::{
+    shell::bindkey,
+    vars::{self, Vars},
+    yml::{Combo, Fragments, GlobalConfig, Keybind, LayerMode, LocalConfig, Profile, Step},
+};
+
+/// Where a keybind or combo was defined.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Origin {
+    Global,
+    /// A profile whose conditions matched the directory.
+    Profile {
+        name: String,
+    },
+    /// A local config file.
+    Local {
+        path: PathBuf,
+    },
+    /// A profile which was inherited by a local config file.
+    Inherited {
+        name: String,
+        path: PathBuf,
+    },
+    /// A profile which was extended by another profile.
+    Extended {
+        name: String,
+        by: Box<Origin>,
+    },
+}
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Origin::Global => write!(f, "global"),
+            Origin::Profile { name } => write!(f, "profile '{name}'"),
+            Origin::Local { path } => write!(f, "local ({})", path.display()),
+            Origin::Inherited { name, path } => {
+                write!(f, "profile '{name}' (inherited by {})", path.display())
+            }
+            Origin::Extended { name, by } => write!(f, "profile '{name}' (extended by {by})"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResolvedKeybind {
+    #[serde(flatten)]
+    pub keybind: Keybind,
+    pub origin: Origin,
+    /// The keybinds for the same key in the layers beneath, which this one replaced.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub shadowed: Vec<ResolvedKeybind>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResolvedCombo {
+    #[serde(flatten)]
+    pub combo: Combo,
+    pub origin: Origin,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResolvedLeader {
+    pub name: String,
+    pub exec_mode: String,
+    pub abbr_mode: String,
+    pub combos: Vec<ResolvedCombo>,
+}
+
+/// A set of keybinds which is applied on top of the layers beneath it.
+struct Layer<'a> {
+    keybinds: &'a [Keybind],
+    origin: Origin,
+    mode: LayerMode,
+}
+
+impl<'a> Layer<'a> {
+    fn new(keybinds: &'a Option<Vec<Keybind>>, origin: Origin, mode: LayerMode) -> Layer<'a> {
+        Layer {
+            keybinds: keybinds.as_deref().unwrap_or_default(),
+            origin,
+            mode,
+        }
+    }
+}
+
+/// Identifies a key by its sequence, so that e.g. `Ctrl-a` and `C-a` are the same key.
+fn key_id(keybind: &Keybind) -> String {
+    match keybind.raw {
+        Some(true) => bindkey::normalize(&keybind.key),
+        _ => bindkey::key_sequence(&keybind.key).unwrap_or_else(|| keybind.key.clone()),
+    }
+}
+
+/// Applies the layers in order, so that a later keybind for a key replaces an earlier one.
+fn apply_layers(layers: Vec<Layer>) -> Vec<ResolvedKeybind> {
+    let mut keymap: Vec<ResolvedKeybind> = vec![];
+
+    for layer in layers {
+        if layer.mode == LayerMode::Override {
+            debug!("{} overrides {} keybinds", layer.origin, keymap.len());
+            keymap.clear();
+        }
+
+        for keybind in layer.keybinds {
+            let resolved = ResolvedKeybind {
+                keybind: keybind.clone(),
+                origin: layer.origin.clone(),
+                shadowed: vec![],
+            };
+
+            match keymap
+                .iter_mut()
+                .find(|r| key_id(&r.keybind) == key_id(keybind))
+            {
+                Some(existing) => {
+                    let mut old = std::mem::replace(existing, resolved);
+                    existing.shadowed = std::mem::take(&mut old.shadowed);
+                    existing.shadowed.push(old);
+                }
+                None => keymap.push(resolved),
+            }
+        }
+    }
+
+    keymap
+}
+
+/// Adds the profiles which `profile` extends, transitively, followed by `profile` itself.
+/// `chain` is the path of profiles being extended, which is used to detect cycles.
+fn visit_profile<'a>(
+    profiles: &[&'a Profile],
+    profile: &'a Profile,
+    chain: &mut Vec<&'a str>,
+    out: &mut Vec<&'a Profile>,
+) -> Result<()> {
+    if chain.contains(&profile.name.as_str()) {
+        chain.push(&profile.name);
+        bail!(
+            "Profiles extend each other in a cycle: {}",
+            chain.join(" -> ")
+        );
+    }
+    if out.iter().any(|p| p.name == profile.name) {
+        return Ok(());
+    }
+
+    chain.push(&profile.name);
+    for name in profile.extends.iter().flatten() {
+        let parent = profiles.iter().find(|p| p.name == *name).ok_or_else(|| {
+            anyhow!(
+                "Profile {:?} extends profile {name:?}, which does not exist",
+                profile.name
+            )
+        })?;
+        visit_profile(profiles, parent, chain, out)?;
+    }
+    chain.pop();
+
+    out.push(profile);
+    Ok(())
+}
+
+/// Expands a profile into the profiles it extends, transitively and in the order they're
+/// applied, followed by the profile itself. A profile which is extended twice is only included
+/// once.
+pub fn expand_profile<'a>(
+    global: &'a GlobalConfig,
+    profile: &'a Profile,
+) -> Result<Vec<&'a Profile>> {
+    let profiles: Vec<&Profile> = global.profiles.iter().flatten().collect();
+    let mut out = vec![];
+
+    visit_profile(&profiles, profile, &mut vec![], &mut out)?;
+    Ok(out)
+}
+
+/// Checks that every profile extends profiles which exist, without a cycle.
+pub fn validate_profiles(global: &GlobalConfig) -> Result<()> {
+    for profile in global.profiles.iter().flatten() {
+        expand_profile(global, profile).map_err(|e| {
+            anyhow!(
+                "{e:#}{}",
+                Fragments::describe(&global.fragments.profiles, &profile.name)
+            )
+        })?;
+    }
+    Ok(())
+}
+
+/// The profiles which apply to the current directory, in the order they're applied: the
+/// matching profiles, then those inherited by the local config, ordered by their priority. Each
+/// profile is preceded by the profiles it extends, and a profile is only applied once.
+fn applied_profiles<'a>(
+    global: &'a Option<GlobalConfig>,
+    local: Option<(&Path, &'a LocalConfig)>,
+) -> Result<Vec<(&'a Profile, Origin)>> {
+    let mut profiles: Vec<(&Profile, Origin)> = vec![];
+
+    if let Some(global) = global {
+        for profile in global.profiles.iter().flatten() {
+            if profile.evaluate_conditions() {
+                info!("Apply profile: {}", profile.name);
+                profiles.push((
+                    profile,
+                    Origin::Profile {
+                        name: profile.name.clone(),
+                    },
+                ));
+            }
+        }
+    }
+
+    if let Some((path, local)) = local
+        && let Some(ref inherits_profiles) = local.inherits
+    {
+        let global_profiles = global
+            .as_ref()
+            .ok_or_else(|| anyhow!("Error: can only use 'inherits' in local config if profiles are defined in global.blz.yml, but the latter seems to be absent"))?
+            .profiles
+            .as_ref()
+            .ok_or_else(|| anyhow!("Error: can only use 'inherits' in local config if profiles are defined in global.blz.yml, but profiles seem to be absent in the latter"))?;
+
+        let global_profiles: fnv::FnvHashMap<&str, &Profile> = global_profiles
+            .iter()
+            .map(|profile| (profile.name.as_str(), profile))
+            .collect();
+
+        for p in inherits_profiles {
+            let prof = global_profiles.get(p.as_str()).ok_or_else(|| anyhow!("Error: Local config inherits profile {p:?} which does not exist in global config"))?;
+
+            debug!("Inherit profile {p:?}");
+
+            // A profile which matches and is also inherited is only applied once.
+            profiles.retain(|(profile, _)| profile.name != *p);
+            profiles.push((
+                prof,
+                Origin::Inherited {
+                    name: p.clone(),
+                    path: path.to_path_buf(),
+                },
+            ));
+        }
+    }
+
+    // The sort is stable, so profiles with the same priority keep their order.
+    profiles.sort_by_key(|(profile, _)| profile.priority.unwrap_or(0));
+
+    let mut applied: Vec<(&Profile, Origin)> = vec![];
+    for (profile, origin) in profiles {
+        // The global config is present whenever there are profiles.
+        let Some(global) = global else { break };
+
+        for p in expand_profile(global, profile)? {
+            if applied.iter().any(|(a, _)| a.name == p.name) {
+                continue;
+            }
+
+            let origin = match p.name == profile.name {
+                true => origin.clone(),
+                false => Origin::Extended {
+                    name: p.name.clone(),
+                    by: Box::new(origin.clone()),
+                },
+            };
+            applied.push((p, origin));
+        }
+    }
+
+    Ok(applied)
+}
+
+/// Resolves the variables which apply to the current directory. The local config's variables
+/// win over those of the profiles (in the order they're applied), which win over the global ones.
+pub fn resolve_vars(
+    global: &Option<GlobalConfig>,
+    local: Option<(&Path, &LocalConfig)>,
+) -> Result<Vars> {
+    let mut vars = Vars::new();
+
+    let global_vars = global
+        .as_ref()
+        .and_then(|g| g.global.as_ref())
+        .and_then(|g| g.vars.as_ref());
+    let profile_vars = applied_profiles(global, local)?
+        .into_iter()
+        .filter_map(|(profile, _)| profile.vars.as_ref());
+    let local_vars = local.and_then(|(_, local)| local.vars.as_ref());
+
+    for layer in global_vars
+        .into_iter()
+        .chain(profile_vars)
+        .chain(local_vars)
+    {
+        vars.extend(layer.iter().map(|(k, v)| (k.clone(), v.clone())));
+    }
+    Ok(vars)
+}
+
+/// Interpolates the variables into the command (or inserted text) of a keybind, and those it
+/// shadowed.
+fn interpolate_keybind(resolved: &mut ResolvedKeybind, vars: &Vars) {
+    if let Some(ref mut command) = resolved.keybind.command {
+        *command = vars::interpolate(command, vars);
+    }
+    for step in resolved.keybind.steps.iter_mut().flatten() {
+        if let Step::Insert { insert } = step {
+            *insert = vars::interpolate(insert, vars);
+        }
+    }
+    for shadowed in &mut resolved.shadowed {
+        interpolate_keybind(shadowed, vars);
+    }
+}
+
+/// Resolves the keybinds which apply to the current directory, with one keybind per key.
+///
+/// The layers are applied in order: the global keybinds, then the profiles which apply (see
+/// [`applied_profiles`]), then the local config. A later keybind for a key replaces an earlier
+/// one, which is kept as shadowed. The variables which apply (see [`resolve_vars`]) are
+/// interpolated into the commands.
+///
+/// The local config is given with its path, if present.
+pub fn resolve_keybinds(
+    global: &Option<GlobalConfig>,
+    local: Option<(&Path, &LocalConfig)>,
+) -> Result<Vec<ResolvedKeybind>> {
+    let mut layers: Vec<Layer> = global_layer(global).into_iter().collect();
+
+    for (profile, origin) in applied_profiles(global, local)? {
+        layers.push(Layer::new(&profile.keybinds, origin, profile.mode));
+    }
+
+    match local {
+        Some((path, local)) => {
+            debug!("Resolve keybinds from local config");
+            layers.push(Layer::new(
+                &local.keybinds,
+                Origin::Local {
+                    path: path.to_path_buf(),
+                },
+                local.mode,
+            ));
+        }
+        None => debug!("No local keybinds found"),
+    }
+
+    let vars = resolve_vars(global, local)?;
+    let mut keymap = apply_layers(layers);
+    for resolved in &mut keymap {
+        interpolate_keybind(resolved, &vars);
+    }
+    Ok(keymap)
+}
+
+/// The layer of the global keybinds, if there is a global config.
+fn global_layer(global: &Option<GlobalConfig>) -> Option<Layer<'_>> {
+    global
+        .as_ref()
+        .and_then(|g| g.global.as_ref())
+        .map(|g| Layer {
+            keybinds: &g.keybinds,
+            origin: Origin::Global,
+            mode: LayerMode::Extend,
+        })
+}
+
+/// Resolves only the global keybinds, with the global variables, so that the result doesn't
+/// depend on the current directory.
+pub fn resolve_global_keybinds(global: &Option<GlobalConfig>) -> Vec<ResolvedKeybind> {
+    let vars = resolve_global_vars(global);
+    let mut keymap = apply_layers(global_layer(global).into_iter().collect());
+    for resolved in &mut keymap {
+        interpolate_keybind(resolved, &vars);
+    }
+    keymap
+}
+
+/// The variables of the global config, without those of the profiles and local config.
+pub fn resolve_global_vars(global: &Option<GlobalConfig>) -> Vars {
+    global
+        .as_ref()
+        .and_then(|g| g.global.as_ref())
+        .and_then(|g| g.vars.clone())
+        .unwrap_or_default()
+}
+
+/// The names of the profiles whose conditions match the current directory.
+pub fn active_profiles(global: &Option<GlobalConfig>) -> Vec<&str> {
+    global
+        .iter()
+        .flat_map(|g| g.profiles.iter().flatten())
+        .filter(|profile| profile.evaluate_conditions())
+        .map(|profile| profile.name.as_str())
+        .collect()
+}
+
+/// Resolves the combos of every leader key, in the order they are defined.
+pub fn resolve_leaders(global: &Option<GlobalConfig>) -> Result<Vec<ResolvedLeader>> {
+    let no_fragments = Fragments::default();
+    let global_fragments = global.as_ref().map_or(&no_fragments, |g| &g.fragments);
+    let leaders = global
+        .as_ref()
+        .and_then(|g| g.global.as_ref())
+        .and_then(|g| g.leader_keys.as_ref());
+
+    leaders
+        .iter()
+        .copied()
+        .flatten()
+        .map(|leader| {
+            let combos = leader.combos.flatten().map_err(|e| {
+                anyhow!(
+                    "Invalid combos for leader {:?}{}: {e:#}",
+                    leader.name(),
+                    Fragments::describe(&global_fragments.leaders, leader.name())
+                )
+            })?;
+
+            Ok(ResolvedLeader {
+                name: leader.name().to_string(),
+                exec_mode: leader.exec_mode.clone(),
+                abbr_mode: leader.abbr_mode.clone(),
+                combos: combos
+                    .into_iter()
+                    .map(|combo| ResolvedCombo {
+                        combo,
+                        origin: Origin::Global,
+                    })
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+#[test]
+fn test_resolve_origins() {
+    let global: GlobalConfig = serde_yml::from_str(
+        r#"
+global:
+  keybinds:
+    - key: Alt-p
+      zle: push-line
+  leader_keys:
+    - name: Leader1
+      exec_mode: Ctrl-s
+      abbr_mode: Alt-s
+      combos: |
+        gs = git status
+profiles:
+  - name: Everywhere
+    conditions:
+      - within: /
+    keybinds:
+      - key: Alt-b
+        command: make
+  - name: Rust
+    keybinds:
+      - key: Alt-b
+        command: cargo build
+"#,
+    )
+    .unwrap();
+    let local: LocalConfig = serde_yml::from_str(
+        r#"
+inherits: [Rust]
+keybinds:
+  - key: F10
+    command: cargo run
+"#,
+    )
+    .unwrap();
+
+    let path = Path::new("/project/.blz.yml");
+    let global = Some(global);
+    let resolved = resolve_keybinds(&global, Some((path, &local))).unwrap();
+
+    let origins: Vec<_> = resolved
+        .iter()
+        .map(|r| (r.keybind.key.as_str(), r.origin.to_string()))
+        .collect();
+    assert_eq!(
+        origins,
+        [
+            ("Alt-p", "global".to_string()),
+            (
+                "Alt-b",
+                "profile 'Rust' (inherited by /project/.blz.yml)".to_string()
+            ),
+            ("F10", "local (/project/.blz.yml)".to_string()),
+        ]
+    );
+    assert_eq!(
+        resolved[1].shadowed[0].keybind.command.as_deref(),
+        Some("make")
+    );
+
+    let leaders = resolve_leaders(&global).unwrap();
+    assert_eq!(leaders[0].combos[0].combo.keys, "gs");
+    assert_eq!(
+        serde_json::to_value(&leaders[0].combos[0]).unwrap()["origin"]["kind"],
+        "global"
+    );
+}
+
+#[test]
+fn test_resolve_layers() {
+    let global: GlobalConfig = serde_yml::from_str(
+        r#"
+global:
+  keybinds:
+    - key: Alt-p
+      zle: push-line
+    - key: Ctrl-b
+      command: make
+profiles:
+  - name: High
+    priority: 10
+    conditions:
+      - within: /
+    keybinds:
+      - key: C-b
+        command: make -j
+  - name: Low
+    conditions:
+      - within: /
+    keybinds:
+      - key: Alt-b
+        command: cargo build
+      - key: Ctrl-b
+        command: cargo build
+"#,
+    )
+    .unwrap();
+    let global = Some(global);
+
+    let keys = |resolved: Vec<ResolvedKeybind>| -> Vec<(String, String)> {
+        resolved
+            .into_iter()
+            .map(|r| (r.keybind.key, r.keybind.command.or(r.keybind.zle).unwrap()))
+            .collect()
+    };
+
+    // The profile with the higher priority wins, even though it's defined first.
+    let resolved = resolve_keybinds(&global, None).unwrap();
+    assert_eq!(resolved[1].shadowed.len(), 2);
+    assert_eq!(
+        keys(resolved),
+        [
+            ("Alt-p".into(), "push-line".into()),
+            ("C-b".into(), "make -j".into()),
+            ("Alt-b".into(), "cargo build".into()),
+        ]
+    );
+
+    let local: LocalConfig = serde_yml::from_str(
+        r#"
+mode: override
+keybinds:
+  - key: F10
+    command: cargo run
+"#,
+    )
+    .unwrap();
+    let resolved = resolve_keybinds(&global, Some((Path::new(".blz.yml"), &local))).unwrap();
+    assert_eq!(keys(resolved), [("F10".into(), "cargo run".into())]);
+}
+
+#[test]
+fn test_resolve_extends() {
+    let global: GlobalConfig = serde_yml::from_str(
+        r#"
+profiles:
+  - name: Common
+    keybinds:
+      - key: F1
+        command: ls
+  - name: Docker
+    extends: [Common]
+    keybinds:
+      - key: Alt-d
+        command: docker ps
+  - name: Rust
+    extends: [Docker, Common]
+    keybinds:
+      - key: F1
+        command: cargo doc
+"#,
+    )
+    .unwrap();
+    validate_profiles(&global).unwrap();
+
+    let local: LocalConfig = serde_yml::from_str("inherits: [Rust]").unwrap();
+    let global = Some(global);
+    let resolved = resolve_keybinds(&global, Some((Path::new(".blz.yml"), &local))).unwrap();
+
+    let origins: Vec<_> = resolved
+        .iter()
+        .map(|r| (r.keybind.key.as_str(), r.origin.to_string()))
+        .collect();
+    assert_eq!(
+        origins,
+        [
+            ("F1", "profile 'Rust' (inherited by .blz.yml)".to_string()),
+            (
+                "Alt-d",
+                "profile 'Docker' (extended by profile 'Rust' (inherited by .blz.yml))".to_string()
+            ),
+        ]
+    );
+    assert_eq!(
+        resolved[0].shadowed[0].origin.to_string(),
+        "profile 'Common' (extended by profile 'Rust' (inherited by .blz.yml))"
+    );
+
+    let cyclic: GlobalConfig = serde_yml::from_str(
+        r#"
+profiles:
+  - name: A
+    extends: [B]
+  - name: B
+    extends: [A]
+"#,
+    )
+    .unwrap();
+    assert_eq!(
+        validate_profiles(&cyclic).unwrap_err().to_string(),
+        "Profiles extend each other in a cycle: A -> B -> A"
+    );
+}
+
+#[test]
+fn test_resolve_vars() {
+    let global: GlobalConfig = serde_yml::from_str(
+        r#"
+global:
+  vars:
+    compose_file: compose.yml
+    context: production
+  keybinds:
+    - key: F8
+      command: docker compose -f ${compose_file} up
+profiles:
+  - name: Staging
+    conditions:
+      - within: /
+    vars:
+      context: staging
+    keybinds:
+      - key: F9
+        command: kubectl --context ${context} get pods
+"#,
+    )
+    .unwrap();
+    let local: LocalConfig = serde_yml::from_str(
+        r#"
+vars:
+  compose_file: infra/compose.yml
+"#,
+    )
+    .unwrap();
+
+    let global = Some(global);
+    let resolved = resolve_keybinds(&global, Some((Path::new(".blz.yml"), &local))).unwrap();
+    let commands: Vec<_> = resolved
+        .iter()
+        .map(|r| r.keybind.command.as_deref().unwrap())
+        .collect();
+    assert_eq!(
+        commands,
+        [
+            "docker compose -f infra/compose.yml up",
+            "kubectl --context staging get pods"
+        ]
+    );
+}
@@ -1,71 +1,53 @@
-use crate::{SHELL, Shell, is_nushell, yml::LeaderKeys};
+use crate::{SHELL, Shell, yml::GlobalConfig};
 use anyhow::Result;
 use log::debug;
+use std::hash::Hasher;
 
+pub mod bindkey;
 pub mod nu_hook;
 pub mod zsh_hook;
 
-pub fn print_leader_state(leaders: &Option<&Vec<LeaderKeys>>) {
-    println!("{}", leaders_to_state(leaders));
-}
-
-pub fn leaders_to_state(leaders: &Option<&Vec<LeaderKeys>>) -> String {
-    leaders.map_or_else(
-        || "none".to_string(),
-        |l| {
-            let mut out = vec![];
-
-            for leader in l {
-                out.push(format!(
-                    "{}_e{}_a{}",
-                    leader.sanitized_name(),
-                    leader.exec_mode,
-                    leader.abbr_mode,
-                ));
-            }
+/// The variable which holds the state of the config which the shell was initialised with.
+pub const STATE_VAR: &str = "BLZ_LEADER_STATE";
 
-            out.sort();
-            out.join("|")
-        },
-    )
+pub fn print_leader_state(global: &Option<GlobalConfig>) {
+    println!("{}", config_state(global));
 }
 
-/// Checks if the leader key state from the env is up to date. Returs an error if not.
-pub fn check_leaders(leaders: &Option<&Vec<LeaderKeys>>) -> Result<()> {
-    let var = std::env::var("BLZ_LEADER_STATE");
-    debug!("Check leader keys: {leaders:?}");
-    debug!("BLZ_LEADER_STATE = {var:?}");
+/// A hash of the global config, which changes whenever anything in the config changes (but not
+/// comments or formatting).
+pub fn config_state(global: &Option<GlobalConfig>) -> String {
+    match global {
+        Some(global) => {
+            let mut hasher = fnv::FnvHasher::default();
+            hasher.write(&serde_json::to_vec(global).unwrap_or_default());
+            format!("{:016x}", hasher.finish())
+        }
+        None => "none".to_string(),
+    }
+}
 
-    let shell = *SHELL.lock().unwrap();
+/// Checks if the state of a shell matches the state of the config. Returns an error if the shell
+/// has no state, which means that the shell hook was never initialised.
+pub fn check_state(state: &str, var: Option<String>) -> Result<bool> {
+    debug!("{STATE_VAR} = {var:?}, expected {state:?}");
 
-    let (which, _or) = match shell {
+    let (which, _or) = match *SHELL.lock().unwrap() {
         Shell::Zsh => (".zshrc", ", or run 'source ~/.zshrc'"),
         Shell::Nu => ("nu config", ""),
     };
 
     match var {
-        Ok(it) => {
-            if it != leaders_to_state(leaders) {
-                // nushell doesn't make it so easy to refresh the environment, so we'll allow an
-                // override to quiet this message.
-                if is_nushell() && std::env::var("BLZ_STFU").is_ok_and(|it| it != "false") {
-                    return Ok(());
-                }
-
-                anyhow::bail!(match shell {
-                    Shell::Zsh =>
-                        "The '.zshrc' needs to be sourced since the leader keys have changed since BLZ was last initialised. \nPlease run 'source ~/.zshrc'.",
-                    Shell::Nu =>
-                        "The nu session needs to be updated since the BLZ leader keys have changed. This requires opening a new shell with a new environment; sourcing the config and 'exec nu' won't work, but you can open a new terminal tab. \nTip: Use '$env.BLZ_STFU = true' in the current session to quiet this message.",
-                })
-            } else {
-                Ok(())
-            }
-        }
-        Err(_) => {
+        Some(it) => Ok(it == state),
+        None => {
             anyhow::bail!(
-                "The 'BLZ_LEADER_STATE' should have been set in the {which}. Please open a new shell{_or}."
+                "The '{STATE_VAR}' should have been set in the {which}. Please open a new shell{_or}."
             )
         }
     }
 }
+
+/// Checks if the state from the env is up to date.
+pub fn check_env_state(state: &str) -> Result<bool> {
+    check_state(state, std::env::var(STATE_VAR).ok())
+}
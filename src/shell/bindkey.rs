@@ -0,0 +1,176 @@
+//! Compares keys with the bindings which zsh already has, as listed by `bindkey -L`.
+//!
+//! Keys are compared in zsh's caret notation, e.g. `^[b` for `Alt-b` and `^A` for `Ctrl-a`.
+
+use crate::keys::{self, KeyOrLeader};
+
+/// A binding from the output of `bindkey -L`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Binding {
+    /// The key sequence, in normalized caret notation.
+    pub key: String,
+    /// The widget, or the string which is inserted for `bindkey -s`.
+    pub target: String,
+    pub is_string: bool,
+}
+
+/// Normalizes a key sequence in zsh's notation, so that equal sequences compare equal.
+pub fn normalize(key: &str) -> String {
+    let mut out = String::new();
+    let mut chars = key.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some('e' | 'E')) => {
+                chars.next();
+                out.push_str("^[");
+            }
+            ('\\', Some(&c)) => {
+                chars.next();
+                out.push(c);
+            }
+            ('^', Some(&c)) => {
+                chars.next();
+                out.push('^');
+                out.push(c.to_ascii_uppercase());
+            }
+            (c, _) => out.push(c),
+        }
+    }
+    out
+}
+
+/// The caret notation of the bytes which a terminal sends for a key.
+pub fn caret_notation(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes)
+        .chars()
+        .map(|c| match c {
+            '\x7f' => "^?".to_string(),
+            c if (c as u32) < 0x20 => format!("^{}", ((c as u8) + 0x40) as char),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+/// The normalized key sequence of a key in the config, e.g. `Alt-b` or `F5`.
+pub fn key_sequence(key: &str) -> Option<String> {
+    match keys::get_key_zsh_representation(key)? {
+        KeyOrLeader::Key(key) | KeyOrLeader::LeaderCombo(key) => Some(normalize(&key)),
+    }
+}
+
+/// The name of a key in the config for a normalized key sequence, e.g. `Alt-b` for `^[b`, if
+/// the key can be used without `raw: true`.
+pub fn config_key(sequence: &str) -> Option<String> {
+    let name = if let Some(function) =
+        (1..=12).find(|n| keys::get_key_name(&format!("F{n}")) == Some(sequence))
+    {
+        format!("F{function}")
+    } else {
+        let mut chars = sequence.chars();
+        match (chars.next(), chars.next(), chars.next(), chars.next()) {
+            (Some('^'), Some('['), Some(c), None) if c.is_ascii_graphic() => format!("Alt-{c}"),
+            (Some('^'), Some(c), None, None) if c.is_ascii_uppercase() => {
+                format!("Ctrl-{}", c.to_ascii_lowercase())
+            }
+            _ => return None,
+        }
+    };
+
+    // Only names which map back to the same sequence can be used.
+    (key_sequence(&name).as_deref() == Some(sequence)).then_some(name)
+}
+
+/// Reads a double-quoted word, returning it (without its escapes resolved) and the rest.
+fn quoted(text: &str) -> Option<(String, &str)> {
+    let text = text.trim_start().strip_prefix('"')?;
+    let mut word = String::new();
+    let mut chars = text.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((word, &text[i + 1..])),
+            '\\' => {
+                word.push(c);
+                word.extend(chars.next().map(|(_, c)| c));
+            }
+            c => word.push(c),
+        }
+    }
+    None
+}
+
+/// Parses the output of `bindkey -L`. Ranges (`bindkey -R`) and other keymaps are skipped.
+pub fn parse_listing(text: &str) -> Vec<Binding> {
+    text.lines()
+        .filter_map(|line| {
+            let rest = line.trim().strip_prefix("bindkey ")?;
+            let (is_string, rest) = match rest.strip_prefix("-s ") {
+                Some(rest) => (true, rest),
+                None => (false, rest),
+            };
+            let (key, rest) = quoted(rest)?;
+
+            let target = match is_string {
+                true => quoted(rest)?.0,
+                false => rest.trim().to_string(),
+            };
+
+            Some(Binding {
+                key: normalize(&key),
+                target,
+                is_string,
+            })
+        })
+        .collect()
+}
+
+/// Whether the binding does anything which would be lost by rebinding the key.
+pub fn is_significant(binding: &Binding) -> bool {
+    binding.is_string || !matches!(binding.target.as_str(), "self-insert" | "undefined-key")
+}
+
+#[test]
+fn test_parse_listing() {
+    let listing = r#"bindkey "^A" beginning-of-line
+bindkey -R "\M-^@"-"\M-^?" self-insert
+bindkey "^[b" backward-word
+bindkey -s "^[l" "ls -la^M"
+bindkey "\"" self-insert"#;
+
+    assert_eq!(
+        parse_listing(listing),
+        [
+            Binding {
+                key: "^A".into(),
+                target: "beginning-of-line".into(),
+                is_string: false,
+            },
+            Binding {
+                key: "^[b".into(),
+                target: "backward-word".into(),
+                is_string: false,
+            },
+            Binding {
+                key: "^[l".into(),
+                target: "ls -la^M".into(),
+                is_string: true,
+            },
+            Binding {
+                key: "\"".into(),
+                target: "self-insert".into(),
+                is_string: false,
+            },
+        ]
+    );
+
+    assert_eq!(key_sequence("Ctrl-a").as_deref(), Some("^A"));
+    assert_eq!(key_sequence("Alt-b").as_deref(), Some("^[b"));
+    assert_eq!(key_sequence("F1").as_deref(), Some("^[OP"));
+    assert_eq!(config_key("^[b").as_deref(), Some("Alt-b"));
+    assert_eq!(config_key("^A").as_deref(), Some("Ctrl-a"));
+    assert_eq!(config_key("^[[15~").as_deref(), Some("F5"));
+    assert_eq!(config_key("^[[1;5A"), None);
+    assert_eq!(caret_notation(b"\x1bb"), "^[b");
+    assert_eq!(caret_notation(b"\x01\x7f"), "^A^?");
+}
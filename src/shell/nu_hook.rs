@@ -1,6 +1,6 @@
-use super::leaders_to_state;
+use super::config_state;
 use crate::{
-    CONFIG_DIR, NU_SOURCE_NAME,
+    CONFIG_DIR, CONFIG_DIR_ARG, CONFIG_DIR_VAR, NU_SOURCE_NAME, Shell,
     keys::{self, NuKey},
     yml::GlobalConfig,
 };
@@ -10,16 +10,65 @@ use std::io::{BufRead, BufReader, Write as write2};
 use std::{fmt::Write, fs::File};
 
 const BLZ_LEADER_PREFIX: &str = "##### BLZ_LEADER_STATE: ";
+const BLZ_NU_SOURCE_HEADER: &str = "##### blaze-keys: start v7";
+
+/// Runs the TUI and applies the result to the commandline (see [`crate::protocol`]).
+const BLZ_NU_LEADER_DEF: &str = r#"
+def _blz_leader_key [leader: string, --abbr] {
+  let tmpfile = (mktemp -p ($env.XDG_RUNTIME_DIR? | default "/tmp"))
+  commandline | save --raw --force $tmpfile
+  if $abbr {
+    blz porcelain leader-key $leader --abbr --tmpfile $tmpfile --read-buffer
+  } else {
+    blz porcelain leader-key $leader --tmpfile $tmpfile --read-buffer
+  }
+  let content = (open --raw $tmpfile)
+  rm $tmpfile
+
+  if ($content | is-empty) {
+    return
+  }
+  let parts = ($content | split row --number 2 "\n\n")
+  let reply = ($parts | first | lines | parse "{key}={value}" | transpose --header-row --as-record)
+  let text = ($parts | get 1? | default "")
+  let text = if ($reply.space? == "1") { $"($text) " } else { $text }
+  let start = (commandline get-cursor)
+
+  match $reply.action? {
+    "exec" => { commandline edit --accept --insert $text }
+    "replace" => { commandline edit --replace $text }
+    "replace-exec" => { commandline edit --accept --replace $text }
+    "script" => {
+      let scripts = (_blz_scripts)
+      if ($text in ($scripts | columns)) {
+        do ($scripts | get $text) (commandline)
+      }
+    }
+    "insert" => {
+      commandline edit --insert $text
+      if ($reply.cursor? != null) {
+        commandline set-cursor ($start + ($reply.cursor | into int))
+      }
+    }
+  }
+}
+"#;
 
 pub fn nu_source_location() -> String {
     CONFIG_DIR.join(NU_SOURCE_NAME).to_str().unwrap().into()
 }
 
-fn read_leader_state_from_file() -> Option<String> {
+/// The state of the config which the nu source file was generated from, if any.
+pub fn read_leader_state_from_file() -> Option<String> {
     let f = File::open(nu_source_location()).ok()?;
-    let b = BufReader::new(f);
+    let mut lines = BufReader::new(f).lines();
+
+    // A file generated by an older version must be regenerated.
+    if lines.next()?.ok()? != BLZ_NU_SOURCE_HEADER {
+        return None;
+    }
 
-    match b.lines().nth(3) {
+    match lines.nth(2) {
         Some(ld_line) => ld_line.map(|value| parse_leader_state(&value)).ok(),
         None => None,
     }
@@ -57,17 +106,69 @@ fn write_to_file(content: &str) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Writes a record of a closure for the script of each combo, which `_blz_leader_key` runs by
+/// name. Each closure gets the current commandline as `$buffer`.
+fn write_scripts(buffer: &mut String, global: &GlobalConfig) -> Result<()> {
+    writeln!(buffer, "\ndef _blz_scripts [] {{\n  {{")?;
+    for script in global.scripts(Shell::Nu) {
+        writeln!(
+            buffer,
+            "    {}: {{|buffer|\n{}\n    }}",
+            keys::script_name(&script),
+            script.trim_end()
+        )?;
+    }
+    writeln!(buffer, "  }}\n}}")?;
+    Ok(())
+}
+
+#[test]
+fn test_write_scripts() {
+    let config: GlobalConfig = serde_yml::from_str(
+        r#"
+global:
+  leader_keys:
+    - name: Leader1
+      exec_mode: Alt-s
+      abbr_mode: Alt-a
+      combos:
+        - keys: s
+          script: BUFFER="sudo $BUFFER"
+        - keys: S
+          script:
+            zsh: BUFFER="sudo $BUFFER"
+            nu: commandline edit --replace $"sudo ($buffer)"
+"#,
+    )
+    .unwrap();
+
+    // Only the nushell script is written; the zsh script would break the whole file.
+    let mut buffer = String::new();
+    write_scripts(&mut buffer, &config).unwrap();
+    let nu = r#"commandline edit --replace $"sudo ($buffer)""#;
+    assert!(buffer.contains(&format!("{}: {{|buffer|\n{nu}\n", keys::script_name(nu))));
+    assert!(!buffer.contains("BUFFER="));
+
+    let combos = &config.global.unwrap().leader_keys.unwrap()[0].combos;
+    assert_eq!(combos.flatten_for(Shell::Nu).unwrap().len(), 1);
+    assert_eq!(combos.flatten_for(Shell::Zsh).unwrap().len(), 2);
+}
+
 /// Generate the file containing the code which adds the nushell keybindings to trigger leader keys.
-pub fn generate_nu_source(global: &Option<GlobalConfig>) -> Result<()> {
+///
+/// Returns whether the file was written, i.e. it was absent or generated from an older config.
+pub fn generate_nu_source(global: &Option<GlobalConfig>) -> Result<bool> {
     let mut buffer = String::new();
 
-    if let Some(g) = global.as_ref().and_then(|g| g.global.as_ref()) {
-        let leader_state = leaders_to_state(&g.leader_keys.as_ref());
+    if let Some(config) = global
+        && let Some(ref g) = config.global
+    {
+        let leader_state = config_state(global);
 
         match read_leader_state_from_file() {
             Some(extant) if extant == leader_state => {
                 debug!("No need to rewrite the nu source file");
-                return Ok(());
+                return Ok(false);
             }
             _ => (),
         };
@@ -78,13 +179,18 @@ pub fn generate_nu_source(global: &Option<GlobalConfig>) -> Result<()> {
             if !leaders.is_empty() {
                 writeln!(
                     &mut buffer,
-                    "##### blaze-keys: start v1\n##### The nu widgets which provide the leader key functionality.\n"
+                    "{BLZ_NU_SOURCE_HEADER}\n##### The nu widgets which provide the leader key functionality.\n"
                 )?;
             }
 
             // Must be on 4th line.
             writeln!(&mut buffer, "{BLZ_LEADER_PREFIX}{leader_state}")?;
 
+            if !leaders.is_empty() {
+                write!(&mut buffer, "{BLZ_NU_LEADER_DEF}")?;
+                write_scripts(&mut buffer, config)?;
+            }
+
             for leader in leaders.iter() {
                 for (_i, k) in [&leader.exec_mode, &leader.abbr_mode].iter().enumerate() {
                     let abbr = _i == 1;
@@ -98,38 +204,43 @@ pub fn generate_nu_source(global: &Option<GlobalConfig>) -> Result<()> {
                         None => anyhow::bail!("Need a modifier key for a leader key trigger"),
                         Some(m) => m,
                     };
-                    let (flag, spacing, accept_flag) = match abbr {
-                        true => ("--abbr ", "commandline edit --insert ' ';", ""),
-                        false => ("", "", "-A"),
-                    };
+                    let flag = if abbr { " --abbr" } else { "" };
 
-                    write!(&mut buffer,
+                    write!(
+                        &mut buffer,
                         "
 $env.config.keybindings ++= [
     {{
       name: blz_{0}
-      modifier: {2}
-      keycode: {3}
+      modifier: {1}
+      keycode: {2}
       mode: emacs
       event: {{
         send: executehostcommand,
-        cmd: \"let tmpfile = (mktemp -p /tmp); blz porcelain leader-key {0} {5} --tmpfile $tmpfile; commandline edit {1} --insert (cat $tmpfile);{4} rm $tmpfile\"
+        cmd: \"_blz_leader_key {0}{3}\"
       }} 
     }}
 ]
 ",
                         leader.sanitized_name(),
-                        accept_flag,
                         modifier,
                         char,
-                        spacing,
                         flag,
                     )?;
                 }
             }
         }
+        // The widgets must use the same config as the blz which generated them.
+        if CONFIG_DIR_ARG.get().is_some() || std::env::var_os(CONFIG_DIR_VAR).is_some() {
+            writeln!(
+                &mut buffer,
+                "\n$env.{CONFIG_DIR_VAR} = r#'{}'#",
+                CONFIG_DIR.display()
+            )?;
+        }
         writeln!(&mut buffer, "##### blaze-keys: end")?;
     }
 
-    write_to_file(&buffer)
+    write_to_file(&buffer)?;
+    Ok(true)
 }
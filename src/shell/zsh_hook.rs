@@ -1,9 +1,15 @@
 use crate::{
+    CONFIG_DIR_ARG, CONFIG_DIR_VAR, Shell, daemon_socket_path,
     keys::{self, KeyOrLeader},
-    yml::{GlobalConfig, LeaderKeys},
+    yml::GlobalConfig,
 };
 
+/// Re-initialises the shell hook, when the global config has changed since it was sourced.
+pub const RELOAD: &str = "source <(blz --zsh-hook)";
+
 fn print_zsh_chpwd_hook() {
+    let socket = daemon_socket_path();
+
     println!(
         "
 ## blaze-keys: start
@@ -14,66 +20,169 @@ fn print_zsh_chpwd_hook() {
 
 ##### The zsh hook which is called on changing directories. #####
 autoload -U add-zsh-hook
+zmodload zsh/net/socket 2>/dev/null
+
+typeset -g BLZ_SOCKET='{}'
+
+# Applies the keybinds with the help of the daemon ('blz porcelain daemon'), if it's running.
+_blz_daemon_blat() {{
+    local fd line out
+    # Only trust a socket which belongs to this user, in a directory which belongs to them.
+    [[ -O $BLZ_SOCKET && -O ${{BLZ_SOCKET:h}} ]] || return 1
+    # The request's fields are separated by tabs, and it ends with a newline.
+    [[ $PWD != *[$'\\t\\n']* ]] || return 1
+    zsocket $BLZ_SOCKET 2>/dev/null || return 1
+    fd=$REPLY
+
+    print -r -u $fd -- \"binds\"$'\\t'\"$PWD\"$'\\t'\"$BLZ_LEADER_STATE\"$'\\t'\"$BLZ_APPLIED_BINDS\"
+    while IFS= read -r -u $fd line; do
+        out+=$line$'\\n'
+    done
+    exec {{fd}}>&-
+
+    eval \"$out\"
+}}
+
+# Records the use of a keybind, when 'blz stats' is enabled.
+_blz_record() {{
+    blz porcelain record-keybind \"$1\" &>/dev/null &!
+}}
+
+# A copy of zsh's keymap from before blz bound any keys, so that the keys which blz no longer
+# binds can be restored. It's kept when the hook is sourced again.
+(( ${{+_blz_keymap_saved}} )) || {{
+    bindkey -N _blz_orig main
+    typeset -g _blz_keymap_saved=1
+}}
+
+# Restores zsh's own binding for a key which blz no longer binds.
+_blz_unbind() {{
+    local binding=$(bindkey -M _blz_orig -L -- \"$1\" 2>/dev/null)
+    bindkey -r -- \"$1\"
+    [[ -n $binding ]] && eval \"${{binding/-M _blz_orig /}}\"
+}}
 
 run_on_cd() {{
-    source <(blz porcelain blat)
+    _blz_daemon_blat || source <(blz porcelain blat --applied \"$BLZ_APPLIED_BINDS\")
 }}
 
 add-zsh-hook chpwd run_on_cd
-source <(blz porcelain --ignore-leader-state blat)
-"
+# The keys which were bound before a reload are passed on, so that they're still restored.
+source <(blz porcelain --ignore-leader-state blat --applied \"$BLZ_APPLIED_BINDS\")
+",
+        socket.display()
+    );
+}
+
+/// Prints a function for the script of each combo, which `_blz_apply_reply` runs by name. They
+/// run inside the leader key's widget, so they can edit `$BUFFER`, `$CURSOR` etc.
+fn print_scripts(global: &GlobalConfig) {
+    for script in global.scripts(Shell::Zsh) {
+        println!(
+            "function {} {{\n{}\n}}\n",
+            keys::script_name(&script),
+            script.trim_end()
+        );
+    }
+}
+
+/// Prints the function which applies the result written by the TUI (see [`crate::protocol`]).
+///
+/// The result is parsed without `eval`, so a tampered tmpfile can't run arbitrary code.
+fn print_apply_reply() {
+    println!(
+        r#"function _blz_apply_reply {{
+  local content=$(<$1) line start=$CURSOR
+  local -A blz_reply
+
+  [[ -z $content ]] && return
+  [[ $content == *$'\n\n'* ]] || content+=$'\n\n'
+
+  for line in ${{(f)${{content%%$'\n\n'*}}}}; do
+    blz_reply[${{line%%=*}}]=${{line#*=}}
+  done
+  local text=${{content#*$'\n\n'}}
+
+  case ${{blz_reply[action]}} in
+    zle)
+      (( ${{+widgets[${{text%% *}}]}} )) && zle ${{=text}}
+      ;;
+    script)
+      [[ $text == _blz_script_* ]] && (( ${{+functions[$text]}} )) && $text
+      ;;
+    replace|replace-exec)
+      BUFFER=$text
+      CURSOR=$#BUFFER
+      [[ ${{blz_reply[action]}} == replace-exec ]] && zle accept-line
+      ;;
+    insert|exec)
+      LBUFFER+=$text
+      [[ ${{blz_reply[space]}} == 1 ]] && LBUFFER+=' '
+      [[ ${{blz_reply[cursor]}} == <-> ]] && CURSOR=$(( start + ${{blz_reply[cursor]}} ))
+      [[ ${{blz_reply[action]}} == exec ]] && zle accept-line
+      ;;
+  esac
+
+  # The global config has changed, so the widgets and keybinds need to be redefined.
+  [[ ${{blz_reply[reload]}} == 1 ]] && {RELOAD}
+
+  return 0
+}}
+"#
     );
 }
 
-pub fn print_export_leaders(leaders: &Option<&Vec<LeaderKeys>>) {
+pub fn print_export_state(global: &Option<GlobalConfig>) {
     println!(
-        "export BLZ_LEADER_STATE='{}'",
-        super::leaders_to_state(leaders)
+        "export {}='{}'",
+        super::STATE_VAR,
+        super::config_state(global)
     );
 }
 
 /// Prints the code required to integrate the program with Zsh.
 pub fn print_zsh_hook(global: &Option<GlobalConfig>) {
+    // The other invocations of blz in this shell must use the same config.
+    if let Some(dir) = CONFIG_DIR_ARG.get() {
+        println!(
+            "export {CONFIG_DIR_VAR}='{}'",
+            dir.display().to_string().replace('\'', r"'\''")
+        );
+    }
+
     print_zsh_chpwd_hook();
 
-    if let Some(g) = global.as_ref().and_then(|g| g.global.as_ref()) {
-        print_export_leaders(&g.leader_keys.as_ref());
+    print_export_state(global);
 
+    if let Some(config) = global
+        && let Some(ref g) = config.global
+    {
         if let Some(ref leaders) = g.leader_keys {
             if !leaders.is_empty() {
                 println!("##### The zsh widgets which provide the leader key functionality. #####");
+                print_apply_reply();
+                print_scripts(config);
             }
             for (index, leader) in leaders.iter().enumerate() {
                 for (_i, k) in [&leader.exec_mode, &leader.abbr_mode].iter().enumerate() {
                     let abbr = _i == 1;
                     let key = keys::get_key_zsh_representation(k);
 
-                    let func_name = format!(
-                        "_zsh_leader{index}{}",
-                        if abbr { "_abbr " } else { "_exec" }
-                    );
+                    let func_name =
+                        format!("_zsh_leader{index}{}", if abbr { "_abbr" } else { "_exec" });
 
                     let key_zsh = match &key {
                         Some(KeyOrLeader::Key(k)) => k,
                         _ => panic!("invalid keybind for leader"),
                     };
-                    let (flag, spacing, zle_accept) = match abbr {
-                        true => ("--abbr ", " ", ""),
-                        false => ("", "", "\n    zle accept-line"),
-                    };
+                    let flag = if abbr { "--abbr " } else { "" };
 
                     println!(
                         "function {} {{
-  tmpfile=$(mktemp)
-  blz porcelain leader-key {} {}--tmpfile $tmpfile < /dev/tty
-  content=$(cat $tmpfile)
-
-  if [[ $content =~ '^zle .*' ]]; then
-    eval $content
-  else
-    LBUFFER+=\"$(cat $tmpfile){}\"{}
-  fi
-
+  local tmpfile=$(TMPDIR=${{XDG_RUNTIME_DIR:-$TMPDIR}} mktemp)
+  print -rn -- $BUFFER > $tmpfile
+  blz porcelain leader-key {} {}--tmpfile $tmpfile --read-buffer < /dev/tty
+  _blz_apply_reply $tmpfile
   rm $tmpfile
 }}
 
@@ -83,14 +192,10 @@ bindkey '{key_zsh}' {func_name}
                         func_name,
                         leader.sanitized_name(),
                         flag,
-                        spacing,
-                        zle_accept,
                     )
                 }
             }
         }
         println!("## blaze-keys: end");
-    } else {
-        print_export_leaders(&None);
     }
 }
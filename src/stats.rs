@@ -0,0 +1,258 @@
+//! Optional statistics of how often each leader combo and keybind is used, which are only ever
+//! stored locally, in the state dir. `blz stats` reports them, and the TUI orders the combos by
+//! frecency (how often and how recently they were used).
+//!
+//! Nothing is recorded until `blz stats --enable` is run.
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    os::fd::AsRawFd,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    STATE_DIR, STATS_FILE_NAME,
+    nodes::{CharWithModifiers, Node, parse_key_sequence},
+};
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Stats {
+    pub enabled: bool,
+    /// The usage of each combo, by the name of the leader key and then by the keys of the combo
+    /// (e.g. `g<A-c>g`).
+    #[serde(default)]
+    pub combos: BTreeMap<String, BTreeMap<String, Usage>>,
+    /// The usage of each keybind, by its key.
+    #[serde(default)]
+    pub keybinds: BTreeMap<String, Usage>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Usage {
+    pub count: u64,
+    /// When it was last used, in seconds since the Unix epoch.
+    pub last: u64,
+}
+
+impl Usage {
+    fn record(&mut self, now: u64) {
+        self.count += 1;
+        self.last = now;
+    }
+
+    /// The count, weighted by how recently it was last used.
+    pub fn frecency(&self, now: u64) -> f64 {
+        let weight = match now.saturating_sub(self.last) / (60 * 60 * 24) {
+            0 => 4.0,
+            1..=6 => 2.0,
+            7..=29 => 1.0,
+            _ => 0.5,
+        };
+        self.count as f64 * weight
+    }
+}
+
+pub fn stats_path() -> PathBuf {
+    STATE_DIR.join(STATS_FILE_NAME)
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// The keys of a combo, in the notation of the config.
+pub fn sequence_keys(sequence: &[CharWithModifiers]) -> String {
+    sequence.iter().map(|c| c.str_short()).collect()
+}
+
+impl Stats {
+    /// Reads the stats, whether or not they're enabled.
+    pub fn read_any() -> Result<Stats> {
+        match std::fs::read_to_string(stats_path()) {
+            Ok(text) => serde_json::from_str(&text)
+                .with_context(|| format!("Failed to parse {:?}", stats_path())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Stats::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {:?}", stats_path())),
+        }
+    }
+
+    /// Reads the stats, if they're enabled.
+    pub fn read() -> Option<Stats> {
+        Stats::read_any().ok().filter(|stats| stats.enabled)
+    }
+
+    pub fn write(&self) -> Result<()> {
+        std::fs::create_dir_all(&*STATE_DIR)?;
+
+        // Another blz may be reading the file, so it's replaced rather than written in place.
+        let tmp = stats_path().with_extension(format!("{}.tmp", std::process::id()));
+        std::fs::write(&tmp, serde_json::to_vec(self)?)?;
+        std::fs::rename(&tmp, stats_path())?;
+        Ok(())
+    }
+
+    /// Reads the stats, applies the update and writes them, while holding a lock on them, so that
+    /// concurrent updates (e.g. combos used in two shells at once) aren't lost.
+    pub fn update<T>(update: impl FnOnce(&mut Stats) -> T) -> Result<T> {
+        let _lock = lock()?;
+        let mut stats = Stats::read_any()?;
+        let result = update(&mut stats);
+        stats.write()?;
+        Ok(result)
+    }
+
+    /// The frecency of each combo of the leader key which has been used.
+    pub fn frecencies(&self, leader: &str) -> Vec<(Vec<CharWithModifiers>, f64)> {
+        let now = now();
+
+        self.combos
+            .get(leader)
+            .into_iter()
+            .flatten()
+            .filter_map(|(keys, usage)| Some((parse_key_sequence(keys).ok()?, usage.frecency(now))))
+            .collect()
+    }
+}
+
+/// Takes an exclusive lock on the stats, which is released when the file is dropped. The stats
+/// file is replaced whenever it's written, so a separate file is locked.
+fn lock() -> Result<File> {
+    std::fs::create_dir_all(&*STATE_DIR)?;
+    let path = stats_path().with_extension("lock");
+    let file = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {path:?}"))?;
+
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("Failed to lock {path:?}"));
+    }
+    Ok(file)
+}
+
+/// Updates the stats, if they're enabled.
+fn record(update: impl FnOnce(&mut Stats, u64)) -> Result<()> {
+    // Checked before taking the lock, so that nothing is created while the stats are disabled.
+    if Stats::read().is_some() {
+        Stats::update(|stats| {
+            if stats.enabled {
+                update(stats, now());
+            }
+        })?;
+    }
+    Ok(())
+}
+
+/// Records a use of the combo of a leader key, if the stats are enabled.
+pub fn record_combo(leader: &str, sequence: &[CharWithModifiers]) -> Result<()> {
+    debug!("Recording the use of {sequence:?} of {leader:?}");
+
+    record(|stats, now| {
+        stats
+            .combos
+            .entry(leader.to_string())
+            .or_default()
+            .entry(sequence_keys(sequence))
+            .or_default()
+            .record(now)
+    })
+}
+
+/// Records a use of the keybind for the key, if the stats are enabled.
+pub fn record_keybind(key: &str) -> Result<()> {
+    record(|stats, now| {
+        stats
+            .keybinds
+            .entry(key.to_string())
+            .or_default()
+            .record(now)
+    })
+}
+
+/// Whether a combo could be added for the sequence, without changing any other combo: nothing is
+/// bound to it or beneath it, and it doesn't pass through a combo which runs as soon as it's
+/// typed.
+pub fn is_free(root: &Node, sequence: &[CharWithModifiers]) -> bool {
+    let mut node = root;
+
+    for key in sequence {
+        if node.command.is_some() && node.children.is_empty() {
+            return false;
+        }
+        match node.children.get(key) {
+            Some(child) => node = child,
+            None => return true,
+        }
+    }
+    false
+}
+
+/// A shorter sequence for a combo, which is made of some of its keys (always including the first),
+/// and is free to use (see [`is_free`]).
+pub fn shorter_sequence(
+    root: &Node,
+    sequence: &[CharWithModifiers],
+) -> Option<Vec<CharWithModifiers>> {
+    let (first, rest) = sequence.split_first()?;
+
+    // Every subset of the other keys, with the fewest keys first. Long combos are skipped, since
+    // the number of subsets grows quickly.
+    if rest.len() > 8 {
+        return None;
+    }
+    let mut subsets: Vec<Vec<CharWithModifiers>> = (0..1u32 << rest.len())
+        .map(|mask| {
+            std::iter::once(*first)
+                .chain(
+                    rest.iter()
+                        .enumerate()
+                        .filter(|(i, _)| mask & (1 << i) != 0)
+                        .map(|(_, c)| *c),
+                )
+                .collect()
+        })
+        .filter(|subset: &Vec<_>| subset.len() < sequence.len())
+        .collect();
+    subsets.sort_by_key(|subset| subset.len());
+
+    subsets.into_iter().find(|subset| is_free(root, subset))
+}
+
+#[test]
+fn test_shorter_sequence() {
+    use crate::yml::Combos;
+
+    let combos: Combos = serde_yml::from_str(
+        "|
+  gs = git status
+  gca = git commit -a
+  gcam = git commit -am
+  gcm = git commit -m
+  d = docker
+",
+    )
+    .unwrap();
+    let root = Node::from_combos(&combos).unwrap();
+    let keys = |s: &str| parse_key_sequence(s).unwrap();
+
+    // 'g' and 'gc' are taken, as are 'gca' and 'gcm'.
+    assert_eq!(shorter_sequence(&root, &keys("gcam")), Some(keys("ga")));
+    assert_eq!(shorter_sequence(&root, &keys("gs")), None);
+    // 'd' runs as soon as it's typed, so nothing can be bound beneath it.
+    assert!(!is_free(&root, &keys("dx")));
+
+    let usage = Usage { count: 3, last: 0 };
+    assert_eq!(usage.frecency(60), 12.0);
+    assert_eq!(usage.frecency(60 * 60 * 24 * 365), 1.5);
+}
@@ -2,13 +2,19 @@ extern crate termion;
 
 use colored::{Color, Colorize};
 use itertools::Itertools;
+use log::warn;
 use std::cmp::Ordering;
 use std::fs::File;
 use std::io::Write;
 use termion::event::Key;
 use termion::input::TermRead;
 
-use crate::nodes::{CharWithModifiers, CharWithModifiersAndValidity, Node};
+use crate::nodes::{
+    CharWithModifiers, CharWithModifiersAndValidity, Modifier, NamedKey, Node, compare_keys,
+};
+use crate::protocol::Reply;
+use crate::stats::{self, Stats};
+use crate::yml::ComboMode;
 
 trait TruncateWithEllipsis {
     fn ellipsis(&self, start: u16, max_len: u16) -> String;
@@ -19,6 +25,7 @@ const ELLIPSIS_LEN: usize = ELLIPSIS.len();
 const FINAL: &str = "final";
 const FINAL_LEN: usize = FINAL.len();
 const CHILD_OFFSET: u16 = 40;
+const NEWLINE: &str = " ↵ ";
 
 impl TruncateWithEllipsis for String {
     fn ellipsis(&self, start_pos: u16, max_pos: u16) -> String {
@@ -34,7 +41,15 @@ impl TruncateWithEllipsis for &str {
     /// * `start_pos` - The position in the TUI at which the string starts.
     /// * `max` - The maximum possible position in the TUI for the string to reach.
     ///
+    /// Multi-line strings are collapsed onto one line, so they can't break the layout.
     fn ellipsis(&self, start_pos: u16, max_pos: u16) -> String {
+        if self.contains('\n') {
+            return self
+                .replace('\n', NEWLINE)
+                .as_str()
+                .ellipsis(start_pos, max_pos);
+        }
+
         let max_len: usize = max_pos.saturating_sub(start_pos) as usize;
         if max_len <= ELLIPSIS_LEN {
             return ELLIPSIS.into();
@@ -97,6 +112,8 @@ fn test_truncate_utf8() {
     assert_eq!("tr", "tr".ellipsis(0, 7));
     assert_eq!("tro", "tro".ellipsis(0, 7));
     assert_eq!("troy", "troy".ellipsis(0, 7));
+
+    assert_eq!("a ↵ b", "a\nb".ellipsis(0, 7));
 }
 
 trait KeyBuffer {
@@ -134,6 +151,16 @@ pub struct Tui<'a> {
     /// Whether an invalid sequence is currently entered.
     invalid: bool,
     abbr: bool,
+    /// Whether the shell hook needs to be initialised again (see [`Reply::reload`]).
+    reload: bool,
+    /// The current command line, which combos can transform (see [`ComboMode::transform`]).
+    line: String,
+    leader: String,
+    /// The keys which lead to the current node.
+    path: Vec<CharWithModifiers>,
+    /// The frecency of each combo which has been used, if the stats are enabled (see
+    /// [`crate::stats`]).
+    frecencies: Vec<(Vec<CharWithModifiers>, f64)>,
 }
 
 struct NodeMetadata<'a> {
@@ -148,7 +175,14 @@ impl<'a> Tui<'a> {
         tmpfile: String,
         node: &'a Node,
         abbr: bool,
+        reload: bool,
+        line: String,
+        leader: String,
     ) -> Self {
+        let frecencies = Stats::read()
+            .map(|stats| stats.frecencies(&leader))
+            .unwrap_or_default();
+
         Tui {
             key_buffer: vec![],
             term,
@@ -158,24 +192,23 @@ impl<'a> Tui<'a> {
             invalid_count: 0,
             invalid: false,
             abbr,
+            reload,
+            line,
+            leader,
+            path: vec![],
+            frecencies,
         }
     }
     pub fn run(mut self, stdin: std::io::Stdin) {
         self.write();
 
         let mut cancelled = false;
-        let mut command = None;
+        let mut selected: Option<&Node> = None;
 
         for c in stdin.keys() {
             let mut key = None;
 
             match c.as_ref().unwrap() {
-                Key::Char('\n') | Key::Char(' ') | Key::Ctrl('M') => {
-                    if let Some(ref cmd) = self.node.command {
-                        command = Some(cmd);
-                        break;
-                    }
-                }
                 Key::Ctrl('c') | Key::Esc => {
                     cancelled = true;
                     break;
@@ -188,6 +221,7 @@ impl<'a> Tui<'a> {
 
                     if self.invalid_count == 0 {
                         self.node = self.parents.pop().unwrap();
+                        self.path.pop();
                     }
                     if let Some(l) = last {
                         self.invalid_count =
@@ -195,16 +229,29 @@ impl<'a> Tui<'a> {
                     }
                     self.write();
                 }
+                Key::Ctrl('M') => {
+                    key = CharWithModifiers::from_key(Key::Char('\n'));
+                }
                 Key::Ctrl(any) => {
                     key = modifier_or_fallback(CharWithModifiers::Ctrl(*any), &self.node.children);
                 }
                 Key::Alt(any) => {
                     key = modifier_or_fallback(CharWithModifiers::Alt(*any), &self.node.children);
                 }
-                Key::Char(any) => {
-                    key = Some((*any).into());
+                other => {
+                    key = CharWithModifiers::from_key(*other);
                 }
-                _ => continue,
+            }
+
+            // Space and Enter (or Tab, if both continue a combo) select the current node.
+            if let Some(k) = key
+                && selects_current_node(k, self.node)
+            {
+                if self.node.command.is_some() && self.node.is_available() {
+                    selected = Some(self.node);
+                    break;
+                }
+                continue;
             }
 
             if let Some(key) = key {
@@ -216,9 +263,14 @@ impl<'a> Tui<'a> {
                     }
                     self.parents.push(self.node);
                     self.node = node;
-
-                    if self.node.children.is_empty() && self.node.command.is_some() {
-                        command = self.node.command.as_ref();
+                    self.path.push(key);
+
+                    // A combo with unresolved placeholders stays greyed out, rather than running.
+                    if self.node.children.is_empty()
+                        && self.node.command.is_some()
+                        && self.node.is_available()
+                    {
+                        selected = Some(self.node);
                         break;
                     }
                     true
@@ -234,16 +286,29 @@ impl<'a> Tui<'a> {
             }
         }
 
-        if let Some(cmd) = command
-            && !cancelled
-        {
-            let mut file = File::create(self.tmpfile).unwrap();
+        let reply = match selected {
+            Some(node) if !cancelled => node.command.as_ref().map(|cmd| {
+                Reply::for_command(cmd, self.effective_mode(node), &self.line, self.abbr)
+            }),
+            _ => None,
+        }
+        .or_else(|| self.reload.then(Reply::cancelled));
+
+        if let Some(mut reply) = reply {
+            let mut file = File::create(&self.tmpfile).unwrap();
+
+            reply.reload = self.reload;
 
-            file.write_all(cmd.as_bytes())
-                .expect("Failed to write all output to tmpfile");
+            write!(file, "{reply}").expect("Failed to write all output to tmpfile");
 
             file.flush().expect("Failed to flush to tmpfile");
         }
+        if selected.is_some()
+            && !cancelled
+            && let Err(e) = stats::record_combo(&self.leader, &self.path)
+        {
+            warn!("Failed to record the use of the combo: {e:?}");
+        }
 
         write!(self.term, "{}", termion::cursor::Show).unwrap();
         write!(self.term, "{}", termion::clear::All).unwrap();
@@ -254,6 +319,28 @@ impl<'a> Tui<'a> {
         std::process::exit(0);
     }
 
+    /// The total frecency of the combos beneath the child of the current node.
+    fn frecency(&self, child: &CharWithModifiers) -> f64 {
+        self.frecencies
+            .iter()
+            .filter(|(sequence, _)| {
+                sequence.len() > self.path.len()
+                    && sequence.starts_with(&self.path)
+                    && sequence[self.path.len()] == *child
+            })
+            .map(|(_, frecency)| frecency)
+            .sum()
+    }
+
+    /// The mode for the command of a node, which may be overridden by the combo.
+    fn effective_mode(&self, node: &Node) -> ComboMode {
+        match node.mode {
+            Some(mode) => mode,
+            None if self.abbr => ComboMode::Abbr,
+            None => ComboMode::Exec,
+        }
+    }
+
     pub fn write(&mut self) {
         let (term_width, term_height) = termion::terminal_size().unwrap();
         let compact_mode = term_width < 80;
@@ -261,8 +348,6 @@ impl<'a> Tui<'a> {
         let width_first: u16 = 30;
         let mut height = 0;
 
-        let space_char: CharWithModifiers = ' '.into();
-
         let current: String = self.key_buffer.display();
 
         let (normal_text, red_text) =
@@ -284,45 +369,57 @@ impl<'a> Tui<'a> {
         )
         .unwrap();
 
-        let mut keys: Vec<&CharWithModifiers> = self.node.children.keys().collect();
-
-        keys.sort_by(|a, b| {
-            let alpha_cmp = a.to_ascii_lowercase().cmp(&b.to_ascii_lowercase());
-
-            if alpha_cmp == std::cmp::Ordering::Equal {
-                a.is_uppercase().cmp(&b.is_uppercase())
-            } else {
-                alpha_cmp
-            }
-        });
-        if self.node.command.is_some() {
-            keys.insert(0, &space_char);
+        let current_node = self.node;
+        // The most frecently used combos come first, if the stats are enabled.
+        let mut keys: Vec<Option<&CharWithModifiers>> = current_node
+            .children
+            .keys()
+            .sorted_by(|a, b| {
+                self.frecency(b)
+                    .total_cmp(&self.frecency(a))
+                    .then_with(|| compare_keys(a, b))
+            })
+            .map(Some)
+            .collect();
+
+        // 'None' represents the current node, which can be selected if it has a command.
+        if current_node.command.is_some() {
+            keys.insert(0, None);
         }
 
-        let mut values = self.node.children.clone();
-        values.insert(' '.into(), self.node.clone());
+        let select_label = current_node
+            .select_key()
+            .map(|key| key.str_short().to_lowercase())
+            .unwrap_or_default();
 
         let mut nodes_drawn = vec![];
 
         // Draw the commands available.
         for (index, key) in keys.iter().enumerate() {
             let index = index as u16;
-            let value = values.get(key).unwrap();
+            let value = match key {
+                Some(key) => current_node.children.get(key).unwrap(),
+                None => current_node,
+            };
             height += 1;
 
             let num_subcommands = value.children.len();
 
-            let command = value
-                .command
-                .as_ref()
-                .unwrap_or(&format!(
+            let command = match (&value.command, &value.description) {
+                (Some(command), _) if !value.is_available() => {
+                    format!("(needs ${{{}}}) {command}", value.unresolved.join("}, ${"))
+                }
+                (Some(command), Some(description)) => format!("{command} -- {description}"),
+                (Some(command), None) => command.clone(),
+                _ => format!(
                     "{} subcommand{}",
                     num_subcommands,
                     if num_subcommands == 1 { ' ' } else { 's' }
-                ))
-                .ellipsis(width_first, width_first + CHILD_OFFSET - 10);
+                ),
+            }
+            .ellipsis(width_first, width_first + CHILD_OFFSET - 10);
 
-            let only_subcommands = CharWithModifiers::Unmodified(' ') == **key;
+            let only_subcommands = key.is_none();
 
             if !only_subcommands {
                 nodes_drawn.push(NodeMetadata {
@@ -351,11 +448,16 @@ impl<'a> Tui<'a> {
                     true => FINAL.on_bright_blue().white().to_string(),
                     false => "".into(),
                 },
-                match only_subcommands {
-                    true => "<space>".to_string().green(),
-                    _ => key.str_short().green(),
+                match (key, value.is_available()) {
+                    (None, true) => select_label.to_string().green(),
+                    (Some(key), true) => key.str_short().green(),
+                    (None, false) => select_label.to_string().bright_black(),
+                    (Some(key), false) => key.str_short().bright_black(),
+                },
+                match value.is_available() {
+                    true => command,
+                    false => command.bright_black().to_string(),
                 },
-                command,
             )
             .unwrap();
         }
@@ -422,7 +524,7 @@ impl<'a> Tui<'a> {
                             .and_then(|v| base.as_ref().map(|b| !v.contains(b)))
                             .unwrap_or(false)
                     }) {
-                        children.insert(0, &it.node);
+                        children.insert(0, it.node);
                     }
 
                     (it, children)
@@ -589,27 +691,66 @@ impl<'a> Tui<'a> {
                 }
             }
 
-            let (msg, color) = match self.abbr {
-                true => (
+            let mode = self.effective_mode(current_node);
+
+            let (msg, color) = match mode {
+                ComboMode::Abbr => (
                     "command will be expanded so you can add arguments",
-                    "abbr".on_color(Color::Cyan),
+                    Color::Cyan,
                 ),
-                false => (
-                    "command will be executed immediately",
-                    "exec".on_color(Color::BrightRed),
+                ComboMode::Exec => ("command will be executed immediately", Color::BrightRed),
+                ComboMode::Insert => (
+                    "command will be inserted without a trailing space",
+                    Color::Magenta,
                 ),
+                ComboMode::Script => ("script will be run on the current line", Color::Yellow),
+                ComboMode::Prefix => ("command will be put before the current line", Color::Blue),
+                ComboMode::Pipe => ("current line will be piped into the command", Color::Green),
+                ComboMode::Wrap => ("current line will be wrapped in the command", Color::Green),
+                ComboMode::Replace => ("command will replace the current line", Color::BrightRed),
             };
 
             // Draw the status line which shows which mode is active.
             write!(
                 self.term,
-                "{}{} mode: {}",
+                "{}{} mode: {}{}",
                 termion::cursor::Goto(1, height + 10),
-                color,
+                mode.name().on_color(color),
                 msg,
+                match current_node.mode {
+                    Some(_) => " (set by this combo)",
+                    None => "",
+                },
             )
             .unwrap();
 
+            if !current_node.is_available() {
+                write!(
+                    self.term,
+                    "{}{}",
+                    termion::cursor::Goto(1, height + 11),
+                    format!(
+                        "Unavailable: set ${{{}}} in 'vars' for this directory",
+                        current_node.unresolved.join("}, ${")
+                    )
+                    .yellow()
+                )
+                .unwrap();
+            } else if let Some(result) = current_node
+                .command
+                .as_ref()
+                .and_then(|command| mode.transform(command, &self.line))
+            {
+                // Preview the line which the combo would produce.
+                write!(
+                    self.term,
+                    "{}Result: {}",
+                    termion::cursor::Goto(1, height + 11),
+                    result.ellipsis(8, term_width).bold()
+                )
+                .unwrap();
+            }
+
             // Draw the help text.
             write!(
                 self.term,
@@ -642,13 +783,52 @@ fn modifier_or_fallback(
         std::hash::BuildHasherDefault<fnv::FnvHasher>,
     >,
 ) -> Option<CharWithModifiers> {
-    if !children.contains_key(&key) {
-        let unmodified = (*key).into();
-
-        if children.contains_key(&unmodified) {
-            return Some(unmodified);
-        }
+    if !children.contains_key(&key)
+        && let Some(unmodified) = key.bare().map(CharWithModifiers::from)
+        && children.contains_key(&unmodified)
+    {
+        return Some(unmodified);
     }
 
     Some(key)
 }
+
+/// Whether the key selects the command of the current node, rather than moving to a child. Space
+/// and Enter both do unless they continue a combo, and otherwise the node's select key (see
+/// [`Node::select_key`]) does.
+fn selects_current_node(key: CharWithModifiers, node: &Node) -> bool {
+    let default = matches!(
+        key,
+        CharWithModifiers::Named(NamedKey::Space | NamedKey::Enter, Modifier::None)
+    ) && !node.children.contains_key(&key);
+
+    default || node.select_key() == Some(key)
+}
+
+#[test]
+fn test_selects_current_node() {
+    use crate::yml::Combos;
+    use CharWithModifiers::Named;
+
+    let combos: Combos =
+        serde_yml::from_str("|\n  g = git\n  g<Space> = git status\n  h = hg\n").unwrap();
+    let root = Node::from_combos(&combos).unwrap();
+    let (g, h) = (&root.children[&'g'.into()], &root.children[&'h'.into()]);
+
+    let space = Named(NamedKey::Space, Modifier::None);
+    let enter = Named(NamedKey::Enter, Modifier::None);
+    let tab = Named(NamedKey::Tab, Modifier::None);
+    assert!(!selects_current_node(space, g));
+    assert!(selects_current_node(enter, g));
+    assert!(selects_current_node(space, h) && selects_current_node(enter, h));
+    assert!(!selects_current_node(tab, h));
+
+    // Space and Enter both continue a combo, so Tab selects 'g'.
+    let combos: Combos =
+        serde_yml::from_str("|\n  g = git\n  g<Space> = git status\n  g<Enter> = git log\n")
+            .unwrap();
+    let root = Node::from_combos(&combos).unwrap();
+    let g = &root.children[&'g'.into()];
+    assert!(!selects_current_node(space, g) && !selects_current_node(enter, g));
+    assert!(selects_current_node(tab, g));
+}
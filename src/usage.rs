@@ -0,0 +1,193 @@
+//! 'blz stats', which reports how often the leader combos and keybinds are used (see
+//! [`blaze_keys::stats`]).
+
+use anyhow::Result;
+use blaze_keys::{
+    nodes::{CharWithModifiers, Node},
+    resolve::ResolvedKeybind,
+    stats::{self, Stats, Usage},
+    yml::GlobalConfig,
+};
+use colored::Colorize;
+
+use crate::doctor::{ok, section, warn};
+
+/// A combo from the config, and how often it was used.
+struct ComboUsage<'a> {
+    leader: &'a str,
+    keys: String,
+    sequence: Vec<CharWithModifiers>,
+    command: &'a str,
+    usage: Option<Usage>,
+}
+
+fn ago(last: u64, now: u64) -> String {
+    match now.saturating_sub(last) / (60 * 60 * 24) {
+        0 => "today".to_string(),
+        1 => "yesterday".to_string(),
+        days => format!("{days} days ago"),
+    }
+}
+
+fn print_combos<'a>(combos: impl Iterator<Item = &'a ComboUsage<'a>>, now: u64) {
+    for combo in combos {
+        let usage = combo.usage.unwrap_or_default();
+        println!(
+            "  {:>5}  {} {:<8} {}  {}",
+            usage.count,
+            combo.leader.dimmed(),
+            combo.keys,
+            combo.command.replace('\n', " ↵ "),
+            format!("(last used {})", ago(usage.last, now)).dimmed()
+        );
+    }
+}
+
+/// Enables, disables or resets the stats, if asked to, and otherwise reports them.
+pub fn run(
+    global: &Option<GlobalConfig>,
+    keybinds: &[ResolvedKeybind],
+    enable: bool,
+    disable: bool,
+    reset: bool,
+    top: usize,
+) -> Result<()> {
+    let path = stats::stats_path();
+
+    if enable || disable || reset {
+        return Stats::update(|stats| {
+            if enable {
+                stats.enabled = true;
+                println!("Recording the uses of combos and keybinds in {path:?}.");
+                println!("Run 'cd .' in open shells, so that their keybinds are recorded too.");
+            }
+            if disable {
+                stats.enabled = false;
+                println!("Stopped recording. Run 'blz stats --reset' to forget what was recorded.");
+            }
+            if reset {
+                stats.combos.clear();
+                stats.keybinds.clear();
+                println!("Forgot the recorded uses of combos and keybinds.");
+            }
+        });
+    }
+
+    let stats = Stats::read_any()?;
+    if !stats.enabled {
+        if stats.combos.is_empty() && stats.keybinds.is_empty() {
+            println!("Nothing has been recorded. Run 'blz stats --enable' to start recording.");
+            return Ok(());
+        }
+        warn("Recording is disabled, so these stats are out of date.");
+    }
+
+    let now = stats::now();
+    let leaders = global
+        .iter()
+        .flat_map(|g| g.global.iter())
+        .flat_map(|g| g.leader_keys.iter().flatten());
+
+    let mut trees = vec![];
+    for leader in leaders {
+        trees.push((leader, Node::from_combos(&leader.combos)?));
+    }
+
+    let mut combos: Vec<ComboUsage> = trees
+        .iter()
+        .flat_map(|(leader, root)| {
+            let recorded = stats.combos.get(&leader.sanitized_name());
+
+            root.commands().into_iter().map(move |(sequence, node)| {
+                let keys = stats::sequence_keys(&sequence);
+                ComboUsage {
+                    leader: leader.name(),
+                    usage: recorded.and_then(|r| r.get(&keys)).copied(),
+                    keys,
+                    sequence,
+                    command: node.command.as_deref().unwrap_or_default(),
+                }
+            })
+        })
+        .collect();
+    combos.sort_by_key(|c| std::cmp::Reverse(c.usage.map(|u| (u.count, u.last))));
+
+    let (used, unused): (Vec<&ComboUsage>, Vec<&ComboUsage>) =
+        combos.iter().partition(|c| c.usage.is_some());
+
+    section("Most used combos");
+    if used.is_empty() {
+        println!("  None yet");
+    }
+    print_combos(used.iter().take(top).copied(), now);
+
+    // The least used, unless they were all listed as the most used.
+    if used.len() > top {
+        section("Least used combos");
+        print_combos(
+            used.iter().rev().take((used.len() - top).min(top)).copied(),
+            now,
+        );
+    }
+
+    section(&format!("Never used combos ({})", unused.len()));
+    if unused.is_empty() {
+        ok("Every combo has been used");
+    }
+    for combo in unused.iter().take(top) {
+        println!(
+            "  {} {:<8} {}",
+            combo.leader.dimmed(),
+            combo.keys,
+            combo.command.replace('\n', " ↵ ")
+        );
+    }
+    if unused.len() > top {
+        println!("  ... and {} more", unused.len() - top);
+    }
+
+    // Shorter sequences for the most used combos, which take the most keypresses.
+    let suggestions: Vec<String> = used
+        .iter()
+        .take(top)
+        .filter(|c| c.sequence.len() > 1)
+        .filter_map(|c| {
+            let (_, root) = trees.iter().find(|(l, _)| l.name() == c.leader)?;
+            let shorter = stats::shorter_sequence(root, &c.sequence)?;
+
+            Some(format!(
+                "{} {} ({}) could be {}",
+                c.leader.dimmed(),
+                c.keys,
+                c.command.replace('\n', " ↵ "),
+                stats::sequence_keys(&shorter).green()
+            ))
+        })
+        .collect();
+    if !suggestions.is_empty() {
+        section("Shorter sequences for frequent combos");
+        for suggestion in suggestions {
+            println!("  {suggestion}");
+        }
+    }
+
+    section("Keybinds in this directory");
+    for resolved in keybinds {
+        let key = &resolved.keybind.key;
+        match stats.keybinds.get(key) {
+            Some(usage) => println!(
+                "  {:>5}  {key:<8} {}  {}",
+                usage.count,
+                resolved.keybind.target(),
+                format!("(last used {})", ago(usage.last, now)).dimmed()
+            ),
+            None => println!(
+                "  {:>5}  {key:<8} {}",
+                0,
+                resolved.keybind.target().dimmed()
+            ),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,113 @@
+//! Variables which are interpolated into the commands of keybinds and combos, as `${name}`.
+//!
+//! Variables are defined in `vars:` maps in the global config, the profiles and the local config
+//! (see [`crate::resolve::resolve_vars`]). Only the `${name}` form is interpolated, so `$name` is
+//! always left for the shell.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+/// Variables by name.
+pub type Vars = BTreeMap<String, String>;
+
+/// A `${name}` placeholder, optionally with a default, as in `${name:-default}`.
+static PLACEHOLDER: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)(:-[^}]*)?\}").unwrap());
+
+/// How many times variables which refer to other variables are expanded, which stops cycles.
+const MAX_DEPTH: usize = 8;
+
+/// Whether placeholders fall back to the environment of this process (see
+/// [`leave_env_to_shell`]).
+static ENV_FALLBACK: AtomicBool = AtomicBool::new(true);
+
+/// Leaves the placeholders which aren't variables for the shell, rather than falling back to the
+/// environment of this process. The daemon was started with a different environment to the
+/// shells it serves.
+pub fn leave_env_to_shell() {
+    ENV_FALLBACK.store(false, Ordering::Relaxed);
+}
+
+/// Replaces each `${name}` placeholder with the variable of that name, or else the environment
+/// variable. Placeholders which are neither are left for the shell.
+pub fn interpolate(text: &str, vars: &Vars) -> String {
+    interpolate_with(text, vars, ENV_FALLBACK.load(Ordering::Relaxed))
+}
+
+fn interpolate_with(text: &str, vars: &Vars, env_fallback: bool) -> String {
+    let mut text = text.to_string();
+
+    // The values of variables may contain placeholders themselves.
+    for _ in 0..MAX_DEPTH {
+        let expanded = PLACEHOLDER.replace_all(&text, |caps: &regex::Captures| {
+            // Even the default is left, since the shell's environment may have the variable.
+            if !env_fallback && !vars.contains_key(&caps[1]) {
+                return caps[0].to_string();
+            }
+            shellexpand::env_with_context_no_errors(&caps[0], |name| {
+                vars.get(name)
+                    .cloned()
+                    .or_else(|| std::env::var(name).ok().filter(|_| env_fallback))
+            })
+            .into_owned()
+        });
+        if expanded == text {
+            break;
+        }
+        text = expanded.into_owned();
+    }
+    text
+}
+
+/// The names in the placeholders which are left in an interpolated text, and which are `declared`
+/// by a `vars:` map somewhere in the config, so they're missing in this directory. Other names,
+/// such as shell variables (`${f}` in a `for` loop), are left for the shell.
+pub fn unresolved(text: &str, declared: &BTreeSet<String>) -> Vec<String> {
+    PLACEHOLDER
+        .find_iter(text)
+        .filter(|m| !m.as_str().contains(":-"))
+        .map(|m| m.as_str()[2..m.len() - 1].to_string())
+        .filter(|name| declared.contains(name))
+        .collect()
+}
+
+#[test]
+fn test_interpolate() {
+    let vars: Vars = [
+        ("compose_file", "infra/compose.yml"),
+        ("compose", "docker compose -f ${compose_file}"),
+    ]
+    .into_iter()
+    .map(|(k, v)| (k.to_string(), v.to_string()))
+    .collect();
+
+    assert_eq!(
+        interpolate("${compose} up -d", &vars),
+        "docker compose -f infra/compose.yml up -d"
+    );
+    assert_eq!(interpolate("echo $compose", &vars), "echo $compose");
+    assert_eq!(
+        interpolate("kubectl --context ${context:-staging}", &vars),
+        "kubectl --context staging"
+    );
+    let interpolated = interpolate("${BLZ_SURELY_UNSET} ${HOME} ${f}", &vars);
+    assert_eq!(
+        interpolated,
+        format!(
+            "${{BLZ_SURELY_UNSET}} {} ${{f}}",
+            std::env::var("HOME").unwrap()
+        )
+    );
+    assert_eq!(
+        interpolate_with("${compose} ${HOME} ${HOME:-~}", &vars, false),
+        "docker compose -f infra/compose.yml ${HOME} ${HOME:-~}"
+    );
+    // Only the names which the config declares are missing; '${f}' is for the shell.
+    let declared = ["BLZ_SURELY_UNSET".to_string()].into();
+    assert_eq!(unresolved(&interpolated, &declared), ["BLZ_SURELY_UNSET"]);
+}
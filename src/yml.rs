@@ -1,57 +1,195 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    path::{Path, PathBuf},
+};
 
-use log::{debug, info};
+use log::debug;
 use serde::*;
 
-use crate::keys::{self, emit_keybinds};
+use crate::Shell;
+use crate::cache::SourceStamp;
+use crate::keys;
+use crate::nodes::Node;
+use crate::vars::Vars;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct GlobalConfig {
+    /// Other files which are merged into this one, before it.
+    pub include: Option<Vec<String>>,
     pub global: Option<Global>,
     pub profiles: Option<Vec<Profile>>,
+    /// The compiled trees of the leader keys, by sanitized name (see [`crate::cache`]).
+    #[serde(skip)]
+    pub leader_trees: BTreeMap<String, Node>,
+    /// The files which leader keys and profiles were merged from (see [`crate::cache`]).
+    #[serde(skip)]
+    pub fragments: Fragments,
+    /// The versions of the files which the config was read from (see [`crate::cache`]).
+    #[serde(skip)]
+    pub sources: Vec<SourceStamp>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct LocalConfig {
-    pub inherits: Option<Vec<String>>,
-    pub keybinds: Option<Vec<Keybind>>,
+/// The files which leader keys and profiles were merged from, by name, when it wasn't the main
+/// config file. Used to point errors at the right file.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Fragments {
+    pub leaders: BTreeMap<String, PathBuf>,
+    pub profiles: BTreeMap<String, PathBuf>,
+}
+
+impl Fragments {
+    /// Describes where a leader key or profile was defined, for an error message.
+    pub fn describe(map: &BTreeMap<String, PathBuf>, name: &str) -> String {
+        match map.get(name) {
+            Some(path) => format!(" (in {path:?})"),
+            None => String::new(),
+        }
+    }
 }
 
 impl GlobalConfig {
-    pub fn emit<T>(&self, emitter: &T) -> anyhow::Result<()>
-    where
-        T: Fn(&Keybind, &str, &Option<String>, &Option<String>) -> anyhow::Result<()>,
-    {
-        info!("Emit global keybinds");
-
-        if let Some(ref global) = self.global {
-            keys::emit_keybinds(&global.keybinds, emitter)?;
-        }
-
-        if let Some(ref profiles) = self.profiles {
-            for profile in profiles {
-                if let Some(ref keybinds) = profile.keybinds
-                    && profile.evaluate_conditions()
-                {
-                    info!("Apply profile: {}", profile.name);
-                    emit_keybinds(keybinds, emitter)?;
+    /// The scripts of every leader combo for the shell (see [`ComboMode::Script`]), without
+    /// duplicates. Combos which fail to parse are skipped, since building the trees reports them.
+    pub fn scripts(&self, shell: Shell) -> Vec<String> {
+        let mut scripts: Vec<String> = self
+            .global
+            .iter()
+            .flat_map(|g| g.leader_keys.iter().flatten())
+            .filter_map(|leader| leader.combos.flatten_for(shell).ok())
+            .flatten()
+            .filter(|combo| combo.mode == Some(ComboMode::Script))
+            .map(|combo| combo.command)
+            .collect();
+        scripts.sort();
+        scripts.dedup();
+        scripts
+    }
+
+    /// The names of the variables in the `vars:` maps of the global config and the profiles. Only
+    /// these can be missing in a directory (see [`crate::vars::unresolved`]).
+    pub fn var_names(&self) -> BTreeSet<String> {
+        let global = self.global.iter().flat_map(|g| g.vars.iter());
+        let profiles = self.profiles.iter().flatten().flat_map(|p| p.vars.iter());
+
+        global
+            .chain(profiles)
+            .flat_map(|vars| vars.keys().cloned())
+            .collect()
+    }
+
+    /// Merges a fragment of the config into this one, as though it was written after it.
+    ///
+    /// Keybinds are appended, and variables replace those with the same name. A leader key or
+    /// profile with a new name is appended, while one with an existing name is merged into it: a
+    /// leader key's combos replace those with the same keys, and a profile's keybinds,
+    /// conditions and extended profiles are appended.
+    ///
+    /// `fragment` is the file which `other` was read from, or None for the main config file.
+    pub fn merge(&mut self, other: GlobalConfig, fragment: Option<&Path>) -> anyhow::Result<()> {
+        let record = |map: &mut BTreeMap<String, PathBuf>, name: &str| {
+            if let Some(fragment) = fragment {
+                map.insert(name.to_string(), fragment.to_path_buf());
+            }
+        };
+
+        if let Some(other) = other.global {
+            let global = self.global.get_or_insert_with(|| Global {
+                vars: None,
+                keybinds: vec![],
+                leader_keys: None,
+            });
+            if let Some(vars) = other.vars {
+                global.vars.get_or_insert_default().extend(vars);
+            }
+            global.keybinds.extend(other.keybinds);
+
+            for leader in other.leader_keys.into_iter().flatten() {
+                let leaders = global.leader_keys.get_or_insert_default();
+                record(&mut self.fragments.leaders, &leader.name);
+
+                match leaders.iter_mut().find(|l| l.name == leader.name) {
+                    Some(existing) => existing.merge(leader)?,
+                    None => leaders.push(leader),
                 }
             }
         }
 
+        for profile in other.profiles.into_iter().flatten() {
+            let profiles = self.profiles.get_or_insert_default();
+            record(&mut self.fragments.profiles, &profile.name);
+
+            match profiles.iter_mut().find(|p| p.name == profile.name) {
+                Some(existing) => existing.merge(profile),
+                None => profiles.push(profile),
+            }
+        }
+
         Ok(())
     }
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LocalConfig {
+    pub inherits: Option<Vec<String>>,
+    /// Variables for `${name}` placeholders, which win over those of the profiles and global.
+    pub vars: Option<Vars>,
+    pub keybinds: Option<Vec<Keybind>>,
+    #[serde(default)]
+    pub mode: LayerMode,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Profile {
     pub name: String,
+    /// The profiles whose keybinds this one builds on, which are applied before it.
+    pub extends: Option<Vec<String>>,
+    /// Variables for `${name}` placeholders, which win over the global ones.
+    pub vars: Option<Vars>,
     pub keybinds: Option<Vec<Keybind>>,
     pub conditions: Option<Vec<Condition>>,
+    /// Profiles with a higher priority are applied later, so their keybinds win.
+    pub priority: Option<i32>,
+    #[serde(default)]
+    pub mode: LayerMode,
+}
+
+/// How the keybinds of a profile or local config combine with the layers beneath it.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LayerMode {
+    /// Add to the keybinds beneath, replacing any for the same keys.
+    #[default]
+    Extend,
+    /// Replace all of the keybinds beneath.
+    Override,
 }
 
 impl Profile {
-    fn evaluate_conditions(&self) -> bool {
+    /// Merges a profile with the same name into this one.
+    fn merge(&mut self, other: Profile) {
+        fn append<T>(mine: &mut Option<Vec<T>>, theirs: Option<Vec<T>>) {
+            if let Some(theirs) = theirs {
+                mine.get_or_insert_default().extend(theirs);
+            }
+        }
+
+        append(&mut self.extends, other.extends);
+        append(&mut self.keybinds, other.keybinds);
+        append(&mut self.conditions, other.conditions);
+
+        if let Some(vars) = other.vars {
+            self.vars.get_or_insert_default().extend(vars);
+        }
+
+        if other.priority.is_some() {
+            self.priority = other.priority;
+        }
+        if other.mode == LayerMode::Override {
+            self.mode = other.mode;
+        }
+    }
+    pub(crate) fn evaluate_conditions(&self) -> bool {
         if let Some(ref conditions) = self.conditions {
             for c in conditions {
                 match c {
@@ -143,6 +281,9 @@ pub enum Condition {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Global {
+    /// Variables for `${name}` placeholders in commands (see [`crate::vars`]).
+    pub vars: Option<Vars>,
+    #[serde(default)]
     pub keybinds: Vec<Keybind>,
     pub leader_keys: Option<Vec<LeaderKeys>>,
 }
@@ -152,13 +293,338 @@ pub struct LeaderKeys {
     name: String,
     pub exec_mode: String,
     pub abbr_mode: String,
-    pub combos: String,
+    pub combos: Combos,
 }
 
 impl LeaderKeys {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     pub fn sanitized_name(&self) -> String {
         self.name.replace(" ", "_")
     }
+
+    /// Merges a leader key with the same name into this one. Its trigger keys win, and its
+    /// combos replace those with the same keys.
+    fn merge(&mut self, other: LeaderKeys) -> anyhow::Result<()> {
+        let name = &self.name;
+        let mut combos = self
+            .combos
+            .flatten()
+            .map_err(|e| anyhow::anyhow!("Invalid combos for leader {name:?}: {e:#}"))?;
+        let other_combos = other
+            .combos
+            .flatten()
+            .map_err(|e| anyhow::anyhow!("Invalid combos for leader {name:?}: {e:#}"))?;
+
+        combos.retain(|combo| !other_combos.iter().any(|o| o.keys == combo.keys));
+        combos.extend(other_combos);
+
+        self.exec_mode = other.exec_mode;
+        self.abbr_mode = other.abbr_mode;
+        self.combos = Combos::List(combos);
+        Ok(())
+    }
+}
+
+/// The combos beneath a leader key. All forms produce the same tree of nodes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Combos {
+    /// One `keys = command` per line.
+    Text(String),
+    /// A list of combo objects.
+    List(Vec<Combo>),
+    /// A nested map which mirrors the tree of keys.
+    Tree(BTreeMap<String, ComboTree>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Combo {
+    pub keys: String,
+    #[serde(default)]
+    pub command: String,
+    /// The body of a shell function which is run instead of a command, with access to the current
+    /// line (see [`ComboMode::Script`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub script: Option<Script>,
+    pub description: Option<String>,
+    pub mode: Option<ComboMode>,
+}
+
+/// The script of a combo. Zsh and nushell scripts can't be shared, so a plain string is a zsh
+/// script, and each shell can be given its own.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum Script {
+    Zsh(String),
+    PerShell(ShellScripts),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ShellScripts {
+    pub zsh: Option<String>,
+    pub nu: Option<String>,
+}
+
+impl Script {
+    /// The script for the shell, if it has one.
+    pub fn for_shell(&self, shell: Shell) -> Option<&str> {
+        match (self, shell) {
+            (Script::Zsh(script), Shell::Zsh) => Some(script),
+            (Script::Zsh(_), Shell::Nu) => None,
+            (Script::PerShell(scripts), Shell::Zsh) => scripts.zsh.as_deref(),
+            (Script::PerShell(scripts), Shell::Nu) => scripts.nu.as_deref(),
+        }
+    }
+}
+
+impl Combo {
+    /// Moves the `script` into the command with [`ComboMode::Script`], so that the combos of every
+    /// form are treated alike. Returns None for a script combo which has no script for the shell.
+    fn normalized(mut self, shell: Shell) -> anyhow::Result<Option<Combo>> {
+        // The text form has no way to say which shell a script is for.
+        let script = match (self.script.take(), self.mode) {
+            (None, Some(ComboMode::Script)) => Some(Script::Zsh(std::mem::take(&mut self.command))),
+            (script, _) => script,
+        };
+
+        match (script, self.command.is_empty()) {
+            (Some(_), false) => anyhow::bail!(
+                "The combo {:?} can't set both 'command' and 'script'",
+                self.keys
+            ),
+            (Some(_), true) if self.mode.is_some_and(|m| m != ComboMode::Script) => {
+                anyhow::bail!("The combo {:?} can't set a 'mode' with 'script'", self.keys)
+            }
+            (Some(script), true) => match script.for_shell(shell) {
+                Some(script) => {
+                    self.command = script.to_string();
+                    self.mode = Some(ComboMode::Script);
+                }
+                None => return Ok(None),
+            },
+            (None, true) => anyhow::bail!("The combo {:?} has no command", self.keys),
+            (None, false) => (),
+        }
+        if self.mode == Some(ComboMode::Wrap) && !self.command.contains(WRAP_PLACEHOLDER) {
+            anyhow::bail!(
+                "The combo {:?} wraps the line, so its command must contain '{WRAP_PLACEHOLDER}' where the line goes",
+                self.keys
+            );
+        }
+        Ok(Some(self))
+    }
+}
+
+/// Where the current line goes in the command of a [`ComboMode::Wrap`] combo.
+const WRAP_PLACEHOLDER: &str = "{}";
+
+/// Overrides the mode of the leader key which was used to trigger a combo.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ComboMode {
+    /// Always execute the command immediately.
+    Exec,
+    /// Always expand the command into the current line, followed by a space.
+    Abbr,
+    /// Always insert the command into the current line, without a trailing space.
+    Insert,
+    /// Run the command as the body of a shell function, which can edit the current line.
+    Script,
+    /// Put the command before the current line, e.g. `sudo`.
+    Prefix,
+    /// Pipe the current line into the command, e.g. `less`.
+    Pipe,
+    /// Put the current line in place of the `{}` in the command, e.g. `$({})`.
+    Wrap,
+    /// Replace the current line with the command.
+    Replace,
+}
+
+impl ComboMode {
+    const ALL: [ComboMode; 8] = [
+        ComboMode::Exec,
+        ComboMode::Abbr,
+        ComboMode::Insert,
+        ComboMode::Script,
+        ComboMode::Prefix,
+        ComboMode::Pipe,
+        ComboMode::Wrap,
+        ComboMode::Replace,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ComboMode::Exec => "exec",
+            ComboMode::Abbr => "abbr",
+            ComboMode::Insert => "insert",
+            ComboMode::Script => "script",
+            ComboMode::Prefix => "prefix",
+            ComboMode::Pipe => "pipe",
+            ComboMode::Wrap => "wrap",
+            ComboMode::Replace => "replace",
+        }
+    }
+
+    /// The line which replaces the current line, for the modes which transform it rather than
+    /// inserting the command. Returns None if the mode needs a line, and it's empty (see
+    /// [`ComboMode::insertion`]).
+    pub fn transform(&self, command: &str, line: &str) -> Option<String> {
+        match self {
+            ComboMode::Prefix | ComboMode::Pipe | ComboMode::Wrap if line.trim().is_empty() => None,
+            ComboMode::Prefix if command.ends_with(char::is_whitespace) => {
+                Some(format!("{command}{line}"))
+            }
+            ComboMode::Prefix => Some(format!("{command} {line}")),
+            ComboMode::Pipe => Some(format!("{} | {command}", line.trim_end())),
+            ComboMode::Wrap => Some(command.replace(WRAP_PLACEHOLDER, line)),
+            ComboMode::Replace => Some(command.to_string()),
+            ComboMode::Exec | ComboMode::Abbr | ComboMode::Insert | ComboMode::Script => None,
+        }
+    }
+
+    /// The text which is inserted instead of transforming an empty line, for the rest of the line
+    /// to be typed, and where to put the cursor (as an offset in chars) if it isn't at the end.
+    /// E.g. ` | less` with the cursor at the start for `@pipe less`.
+    pub fn insertion(&self, command: &str) -> Option<(String, Option<usize>)> {
+        match self {
+            ComboMode::Prefix if command.ends_with(char::is_whitespace) => {
+                Some((command.to_string(), None))
+            }
+            ComboMode::Prefix => Some((format!("{command} "), None)),
+            ComboMode::Pipe => Some((format!(" | {command}"), Some(0))),
+            ComboMode::Wrap => {
+                let at = command.find(WRAP_PLACEHOLDER)?;
+                Some((
+                    command.replace(WRAP_PLACEHOLDER, ""),
+                    Some(command[..at].chars().count()),
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    /// Splits a mode prefix from a command in the text form, e.g. `@abbr git commit -m`.
+    fn strip_prefix(command: &str) -> (Option<ComboMode>, &str) {
+        for mode in ComboMode::ALL {
+            if let Some(rest) = command
+                .strip_prefix('@')
+                .and_then(|c| c.strip_prefix(mode.name()))
+                && rest.starts_with(char::is_whitespace)
+            {
+                return (Some(mode), rest.trim_start());
+            }
+        }
+
+        (None, command)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ComboTree {
+    Command(String),
+    Node(ComboNode),
+    Branch(BTreeMap<String, ComboTree>),
+}
+
+/// A node in the tree form which has a command, and possibly metadata or children of its own.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ComboNode {
+    #[serde(default)]
+    pub command: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub script: Option<Script>,
+    pub description: Option<String>,
+    pub mode: Option<ComboMode>,
+    pub children: Option<BTreeMap<String, ComboTree>>,
+}
+
+impl Combos {
+    /// Flattens the combos into a list, regardless of the form used in the config, for the shell
+    /// which blz is running for.
+    pub fn flatten(&self) -> anyhow::Result<Vec<Combo>> {
+        self.flatten_for(crate::shell())
+    }
+
+    /// Flattens the combos into a list, leaving out scripts which the shell has no version of.
+    pub fn flatten_for(&self, shell: Shell) -> anyhow::Result<Vec<Combo>> {
+        let mut out = vec![];
+
+        match self {
+            Combos::Text(text) => {
+                for (keys, command) in keys::parse_combos(text)? {
+                    let (mode, command) = ComboMode::strip_prefix(&command);
+
+                    out.extend(
+                        Combo {
+                            keys,
+                            command: command.to_string(),
+                            script: None,
+                            description: None,
+                            mode,
+                        }
+                        .normalized(shell)?,
+                    );
+                }
+            }
+            Combos::List(list) => {
+                for combo in list {
+                    keys::validate_combo_keys(&combo.keys)?;
+                    out.extend(combo.clone().normalized(shell)?);
+                }
+            }
+            Combos::Tree(tree) => flatten_tree("", tree, shell, &mut out)?,
+        }
+
+        Ok(out)
+    }
+}
+
+fn flatten_tree(
+    prefix: &str,
+    tree: &BTreeMap<String, ComboTree>,
+    shell: Shell,
+    out: &mut Vec<Combo>,
+) -> anyhow::Result<()> {
+    for (keys, value) in tree {
+        let keys = format!("{prefix}{keys}");
+        keys::validate_combo_keys(&keys)?;
+
+        match value {
+            ComboTree::Command(command) => out.push(Combo {
+                keys,
+                command: command.clone(),
+                script: None,
+                description: None,
+                mode: None,
+            }),
+            ComboTree::Node(node) => {
+                out.extend(
+                    Combo {
+                        keys: keys.clone(),
+                        command: node.command.clone(),
+                        script: node.script.clone(),
+                        description: node.description.clone(),
+                        mode: node.mode,
+                    }
+                    .normalized(shell)?,
+                );
+
+                if let Some(ref children) = node.children {
+                    flatten_tree(&keys, children, shell, out)?;
+                }
+            }
+            ComboTree::Branch(children) => flatten_tree(&keys, children, shell, out)?,
+        }
+    }
+
+    Ok(())
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -166,5 +632,196 @@ pub struct Keybind {
     pub key: String,
     pub command: Option<String>,
     pub zle: Option<String>,
+    /// A sequence of steps, which is run by a generated zle widget instead of `command` or `zle`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub steps: Option<Vec<Step>>,
+    /// Inserts the command at the cursor, rather than running it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub insert_only: Option<bool>,
+    /// The body of a zsh function, which is run by a generated zle widget.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub script: Option<String>,
     pub raw: Option<bool>,
 }
+
+/// What the generated zle widget of a keybind runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Widget {
+    Steps(Vec<Step>),
+    Script(String),
+}
+
+/// A step of a keybind with `steps`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Step {
+    /// Runs a zle widget, e.g. `push-line`.
+    Zle {
+        zle: String,
+    },
+    /// Inserts text at the cursor.
+    Insert {
+        insert: String,
+    },
+    Action(Action),
+}
+
+/// A step which is written as a single word.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// Runs the command line, like pressing Enter.
+    Accept,
+}
+
+impl Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Step::Zle { zle } => write!(f, "zle {zle}"),
+            Step::Insert { insert } => write!(f, "insert {insert:?}"),
+            Step::Action(Action::Accept) => write!(f, "accept"),
+        }
+    }
+}
+
+impl Keybind {
+    /// What the keybind runs, if it needs a generated widget rather than a plain binding: for
+    /// `steps` or `script`, or a command with `insert_only`.
+    pub fn widget(&self) -> anyhow::Result<Option<Widget>> {
+        if let Some(ref script) = self.script {
+            if self.command.is_some()
+                || self.zle.is_some()
+                || self.steps.is_some()
+                || self.insert_only.is_some()
+            {
+                anyhow::bail!(
+                    "The config item for '{}' can't set 'script' with 'command', 'zle', 'steps' or 'insert_only'",
+                    self.key
+                );
+            }
+            return Ok(Some(Widget::Script(script.clone())));
+        }
+
+        match (&self.steps, &self.command, &self.zle, self.insert_only) {
+            (Some(_), Some(_), _, _) | (Some(_), _, Some(_), _) => anyhow::bail!(
+                "The config item for '{}' can't set 'steps' with 'command' or 'zle'",
+                self.key
+            ),
+            (Some(steps), None, None, None) => Ok(Some(Widget::Steps(steps.clone()))),
+            (None, Some(command), None, Some(true)) => {
+                Ok(Some(Widget::Steps(vec![Step::Insert {
+                    insert: command.clone(),
+                }])))
+            }
+            (_, _, _, Some(_)) if self.command.is_none() || self.zle.is_some() => anyhow::bail!(
+                "The config item for '{}' can only set 'insert_only' with 'command'",
+                self.key
+            ),
+            _ => Ok(None),
+        }
+    }
+
+    /// What the keybind does, for display.
+    pub fn target(&self) -> String {
+        if let Some(ref script) = self.script {
+            return format!("script {}", script.trim().replace('\n', "; "));
+        }
+
+        match (&self.command, &self.zle, &self.steps) {
+            (Some(command), _, _) if self.insert_only == Some(true) => {
+                format!("insert {command:?}")
+            }
+            (Some(command), _, _) => command.clone(),
+            (None, Some(zle), _) => format!("zle {zle}"),
+            (None, None, Some(steps)) => steps
+                .iter()
+                .map(|step| step.to_string())
+                .collect::<Vec<_>>()
+                .join("; "),
+            (None, None, None) => String::new(),
+        }
+    }
+}
+
+#[test]
+fn test_merge_fragments() {
+    let parse = |yml: &str| -> GlobalConfig { serde_yml::from_str(yml).unwrap() };
+
+    let mut config = GlobalConfig::default();
+    config
+        .merge(
+            parse(
+                r#"
+global:
+  keybinds:
+    - key: Alt-p
+      zle: push-line
+  leader_keys:
+    - name: L
+      exec_mode: Alt-s
+      abbr_mode: Alt-a
+      combos: |
+        gs = git status
+        gp = git push
+profiles:
+  - name: Rust
+    keybinds:
+      - key: Alt-b
+        command: cargo build
+"#,
+            ),
+            None,
+        )
+        .unwrap();
+    config
+        .merge(
+            parse(
+                r#"
+global:
+  leader_keys:
+    - name: L
+      exec_mode: Ctrl-s
+      abbr_mode: Alt-a
+      combos: "gp = git push --force-with-lease"
+profiles:
+  - name: Rust
+    priority: 2
+    keybinds:
+      - key: F9
+        command: cargo test
+"#,
+            ),
+            Some(Path::new("conf.d/team.yml")),
+        )
+        .unwrap();
+
+    let global = config.global.as_ref().unwrap();
+    assert_eq!(global.keybinds.len(), 1);
+
+    let leaders = global.leader_keys.as_ref().unwrap();
+    assert_eq!(leaders.len(), 1);
+    assert_eq!(leaders[0].exec_mode, "Ctrl-s");
+    let combos: Vec<_> = leaders[0]
+        .combos
+        .flatten()
+        .unwrap()
+        .into_iter()
+        .map(|c| (c.keys, c.command))
+        .collect();
+    assert_eq!(
+        combos,
+        [
+            ("gs".into(), "git status".into()),
+            ("gp".into(), "git push --force-with-lease".into()),
+        ]
+    );
+
+    let profiles = config.profiles.as_ref().unwrap();
+    assert_eq!(profiles.len(), 1);
+    assert_eq!(profiles[0].keybinds.as_ref().unwrap().len(), 2);
+    assert_eq!(profiles[0].priority, Some(2));
+    assert_eq!(
+        Fragments::describe(&config.fragments.leaders, "L"),
+        r#" (in "conf.d/team.yml")"#
+    );
+}